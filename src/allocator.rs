@@ -0,0 +1,81 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Keeps track of the memory allocated by the server, reported by `INFO memory`.
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        USED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            match new_size > layout.size() {
+                true => grow(new_size - layout.size()),
+                false => {
+                    USED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+                }
+            }
+        }
+        new
+    }
+}
+
+fn grow(size: usize) {
+    let used = USED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(used, Ordering::Relaxed);
+}
+
+/// Bytes currently allocated.
+pub fn used() -> usize {
+    USED.load(Ordering::Relaxed)
+}
+
+/// Most bytes allocated at once since the start.
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Resident set size of the process, only known on Linux.
+pub fn resident() -> Option<usize> {
+    const PAGE_SIZE: usize = 4096;
+
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * PAGE_SIZE)
+}
+
+/// Physical memory of the machine, only known on Linux.
+pub fn system_total() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|it| it.starts_with("MemTotal:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
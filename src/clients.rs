@@ -0,0 +1,155 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+use tokio::{
+    select,
+    sync::Notify,
+    time::{sleep_until, Instant},
+};
+
+use crate::{config::ClientClass, state::ConnectionState};
+
+pub type SharedClients = Arc<Clients>;
+
+/// Registry of connected clients, used by `CLIENT` commands.
+#[derive(Default)]
+pub struct Clients {
+    connections: Mutex<BTreeMap<u64, ConnectionState>>,
+    pause: Mutex<Option<Pause>>,
+    unpaused: Notify,
+    connections_received: AtomicU64,
+    rejected_connections: AtomicU64,
+    net_input_bytes: AtomicU64,
+    net_output_bytes: AtomicU64,
+}
+
+/// Counters of `INFO stats`, reset by `CONFIG RESETSTAT`.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientStats {
+    pub connections_received: u64,
+    pub rejected_connections: u64,
+    pub net_input_bytes: u64,
+    pub net_output_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+    writes_only: bool,
+}
+
+impl Clients {
+    /// Adds the connection until the returned registration is dropped.
+    pub fn register(self: &Arc<Self>, state: ConnectionState) -> Registration {
+        let id = state.id();
+        self.connections.lock().insert(id, state);
+        self.connections_received.fetch_add(1, Ordering::Relaxed);
+
+        Registration {
+            clients: self.clone(),
+            id,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.lock().len()
+    }
+
+    /// Connected clients that aren't replicas.
+    pub fn normal_len(&self) -> usize {
+        self.connections
+            .lock()
+            .values()
+            .filter(|it| it.class() == ClientClass::Normal)
+            .count()
+    }
+
+    /// Connected clients ordered by id.
+    pub fn list(&self) -> Vec<ConnectionState> {
+        self.connections.lock().values().cloned().collect()
+    }
+
+    /// A connection was closed right away, e.g. because of `maxclients`.
+    pub fn record_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_input(&self, bytes: usize) {
+        self.net_input_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_output(&self, bytes: usize) {
+        self.net_output_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ClientStats {
+        ClientStats {
+            connections_received: self.connections_received.load(Ordering::Relaxed),
+            rejected_connections: self.rejected_connections.load(Ordering::Relaxed),
+            net_input_bytes: self.net_input_bytes.load(Ordering::Relaxed),
+            net_output_bytes: self.net_output_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.connections_received.store(0, Ordering::Relaxed);
+        self.rejected_connections.store(0, Ordering::Relaxed);
+        self.net_input_bytes.store(0, Ordering::Relaxed);
+        self.net_output_bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// Suspends commands until `until`, only writes when `writes_only` is set.
+    /// An active pause is never shortened or relaxed by a new one.
+    pub fn pause(&self, until: Instant, writes_only: bool) {
+        let mut pause = self.pause.lock();
+        *pause = Some(match *pause {
+            Some(current) if current.until > Instant::now() => Pause {
+                until: current.until.max(until),
+                writes_only: current.writes_only && writes_only,
+            },
+            _ => Pause { until, writes_only },
+        });
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// Waits while commands of this kind are paused.
+    pub async fn paused(&self, write: bool) {
+        loop {
+            let unpaused = self.unpaused.notified();
+            let until = match *self.pause.lock() {
+                Some(pause) if pause.until > Instant::now() && (write || !pause.writes_only) => {
+                    pause.until
+                }
+                _ => return,
+            };
+
+            select! {
+                _ = unpaused => {}
+                _ = sleep_until(until) => {}
+            }
+        }
+    }
+}
+
+pub struct Registration {
+    clients: SharedClients,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.clients.connections.lock().remove(&self.id);
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{
+    clients::SharedClients,
+    config::ClientClass,
+    encoding::resp3::Verbatim,
+    error::RedisError,
+    request::{Arg, Extension, Request},
+    response::{IntoResponse, Resp, Response},
+    state::{is_valid_name, ConnectionState},
+};
+
+/// `CLIENT ID`
+pub async fn id(request: Request) -> u64 {
+    request.state().id()
+}
+
+/// `CLIENT INFO`
+pub async fn info(request: Request) -> impl IntoResponse {
+    Resp(Verbatim::txt(request.state().describe() + "\n"))
+}
+
+/// `CLIENT GETNAME`
+pub async fn getname(request: Request) -> Option<Bytes> {
+    request.state().name().map(Bytes::from)
+}
+
+/// `CLIENT SETNAME <name>`
+pub async fn setname(request: Request, Arg(name): Arg<1>) -> Result<&'static str, RedisError> {
+    if !is_valid_name(&name) {
+        return Err(RedisError::InvalidClientName);
+    }
+    request.state().set_name(name);
+    Ok("OK")
+}
+
+/// `CLIENT KILL <ip:port>` or `CLIENT KILL <filter> <value> ...`
+pub async fn kill(
+    Extension(clients): Extension<SharedClients>,
+    request: Request,
+) -> Result<Response, RedisError> {
+    let [addr] = &request.args[..] else {
+        return Ok(kill_filtered(&clients, request.state(), &request.args)?.into_response());
+    };
+
+    // several clients can share an address over unix sockets, all of them are killed
+    let killed = clients
+        .list()
+        .into_iter()
+        .filter(|it| it.addr().to_string() == *addr)
+        .inspect(|it| it.killed().cancel())
+        .count();
+    match killed {
+        0 => Err(RedisError::NoSuchClient),
+        _ => Ok("OK".into_response()),
+    }
+}
+
+/// `CLIENT PAUSE <timeout> [WRITE|ALL]`
+pub async fn pause(
+    Extension(clients): Extension<SharedClients>,
+    request: Request,
+) -> Result<&'static str, RedisError> {
+    let [timeout, mode @ ..] = &request.args[..] else {
+        return Err(RedisError::WrongArity(request.command));
+    };
+    let timeout: u64 = timeout.parse().map_err(|_| RedisError::InvalidTimeout)?;
+    let writes_only = match mode {
+        [] => false,
+        [mode] if mode.eq_ignore_ascii_case("all") => false,
+        [mode] if mode.eq_ignore_ascii_case("write") => true,
+        _ => return Err(RedisError::Syntax),
+    };
+    clients.pause(Instant::now() + Duration::from_millis(timeout), writes_only);
+    Ok("OK")
+}
+
+/// `CLIENT UNPAUSE`
+pub async fn unpause(Extension(clients): Extension<SharedClients>) -> &'static str {
+    clients.unpause();
+    "OK"
+}
+
+/// `CLIENT NO-EVICT <ON|OFF>`
+pub async fn no_evict(request: Request, Arg(mode): Arg<1>) -> Result<&'static str, RedisError> {
+    match mode.to_lowercase().as_str() {
+        "on" => request.state().set_no_evict(true),
+        "off" => request.state().set_no_evict(false),
+        _ => return Err(RedisError::Syntax),
+    }
+    Ok("OK")
+}
+
+/// `CLIENT LIST [TYPE type] [ID id [id ...]]`
+pub async fn list(
+    Extension(clients): Extension<SharedClients>,
+    request: Request,
+) -> Result<impl IntoResponse, RedisError> {
+    let mut connections = clients.list();
+    match &request.args[..] {
+        [] => {}
+        [option, class] if option.eq_ignore_ascii_case("type") => {
+            let class = parse_class(class)?;
+            connections.retain(|it| Some(it.class()) == class);
+        }
+        [option, ids @ ..] if option.eq_ignore_ascii_case("id") && !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|it| it.parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()?;
+            connections.retain(|it| ids.contains(&it.id()));
+        }
+        _ => return Err(RedisError::Syntax),
+    }
+
+    let output = connections
+        .iter()
+        .map(|it| it.describe() + "\n")
+        .collect::<String>();
+    Ok(Resp(Verbatim::txt(output)))
+}
+
+/// Kills the clients matching all the filters, returns their number.
+// `Option::is_none_or` needs a newer toolchain than the one pinned in codecrafters.yml
+#[allow(clippy::unnecessary_map_or)]
+fn kill_filtered(
+    clients: &SharedClients,
+    connection: &ConnectionState,
+    args: &[String],
+) -> Result<usize, RedisError> {
+    let mut id = None;
+    let mut addr = None;
+    let mut class = None;
+    let mut skip_me = true;
+    let mut max_age = None;
+
+    for pair in args.chunks(2) {
+        let [option, value] = pair else {
+            return Err(RedisError::Syntax);
+        };
+        match option.to_lowercase().as_str() {
+            "id" => id = Some(value.parse::<u64>()?),
+            "addr" => addr = Some(value.clone()),
+            "type" => class = Some(parse_class(value)?),
+            "skipme" => {
+                skip_me = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(RedisError::Syntax),
+                }
+            }
+            "maxage" => max_age = Some(value.parse::<u64>()?),
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+
+    let killed = clients
+        .list()
+        .into_iter()
+        .filter(|it| id.map_or(true, |id| it.id() == id))
+        .filter(|it| {
+            addr.as_ref()
+                .map_or(true, |addr| it.addr().to_string() == *addr)
+        })
+        .filter(|it| class.map_or(true, |class| Some(it.class()) == class))
+        .filter(|it| max_age.map_or(true, |max_age| it.age() >= max_age))
+        .filter(|it| !skip_me || it.id() != connection.id())
+        .inspect(|it| it.killed().cancel())
+        .count();
+
+    Ok(killed)
+}
+
+/// `None` stands for the master link and pub/sub clients, neither is ever a registered client.
+fn parse_class(value: &str) -> Result<Option<ClientClass>, RedisError> {
+    match value.to_lowercase().as_str() {
+        "normal" => Ok(Some(ClientClass::Normal)),
+        "replica" | "slave" => Ok(Some(ClientClass::Replica)),
+        "master" | "pubsub" => Ok(None),
+        _ => Err(RedisError::UnknownClientType(value.to_owned())),
+    }
+}
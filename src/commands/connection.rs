@@ -0,0 +1,82 @@
+use serde::Serialize;
+
+use crate::{
+    commands::info::REDIS_VERSION,
+    encoding::{resp3::Map, Protocol},
+    error::RedisError,
+    replication::{NodeRole, ReplicationState},
+    request::{Extension, Request},
+    response::{IntoResponse, Resp},
+    state::is_valid_name,
+};
+
+#[derive(Serialize)]
+struct Hello {
+    server: &'static str,
+    version: &'static str,
+    proto: u8,
+    id: u64,
+    mode: &'static str,
+    role: &'static str,
+    modules: Vec<Map<String, String>>,
+}
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+pub async fn hello(
+    Extension(state): Extension<ReplicationState>,
+    request: Request,
+) -> Result<impl IntoResponse, RedisError> {
+    let connection = request.state();
+    let mut args = request.args.iter();
+
+    let protocol = match args.next().map(|it| it.parse::<u8>()) {
+        None => connection.protocol(),
+        Some(Ok(2)) => Protocol::Resp2,
+        Some(Ok(3)) => Protocol::Resp3,
+        Some(_) => return Err(RedisError::NoProto),
+    };
+
+    let mut name = None;
+    while let Some(option) = args.next() {
+        match option.to_lowercase().as_str() {
+            "auth" => {
+                let (Some(user), Some(_password)) = (args.next(), args.next()) else {
+                    return Err(RedisError::Syntax);
+                };
+                // there are no users or passwords configured besides the default user
+                if user != "default" {
+                    return Err(RedisError::WrongPass);
+                }
+            }
+            "setname" => {
+                let value = args.next().ok_or(RedisError::Syntax)?;
+                if !is_valid_name(value) {
+                    return Err(RedisError::InvalidClientName);
+                }
+                name = Some(value.clone());
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+
+    connection.set_protocol(protocol);
+    if let Some(name) = name {
+        connection.set_name(name);
+    }
+
+    Ok(Resp(Hello {
+        server: "redis",
+        version: REDIS_VERSION,
+        proto: match protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        },
+        id: connection.id(),
+        mode: "standalone",
+        role: match state.role() {
+            NodeRole::Master => "master",
+            NodeRole::Replica => "replica",
+        },
+        modules: vec![],
+    }))
+}
@@ -0,0 +1,341 @@
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    allocator, args,
+    clients::SharedClients,
+    config::Config,
+    encoding::resp3::Verbatim,
+    engine::SharedEngine,
+    replication::{ReplicationState, SharedTopology, Topology},
+    request::Extension,
+    response::{IntoResponse, Resp},
+    stats::SharedStats,
+};
+
+/// Version reported to clients, the commands follow this version of redis.
+pub const REDIS_VERSION: &str = "7.2.0";
+
+/// Sections in the order they are printed, along with whether they are part of `default`.
+const SECTIONS: &[(&str, bool)] = &[
+    ("server", true),
+    ("clients", true),
+    ("memory", true),
+    ("persistence", true),
+    ("stats", true),
+    ("replication", true),
+    ("cpu", true),
+    ("commandstats", false),
+    ("errorstats", true),
+    ("keyspace", true),
+];
+
+args! {
+    /// `INFO [section [section ...]]`
+    pub struct InfoArgs {
+        #[rest]
+        sections: Vec<String>,
+    }
+}
+
+impl InfoArgs {
+    /// Whether `section` is requested, `default` sections are included when none is.
+    fn includes(&self, section: &str, default: bool) -> bool {
+        if self.sections.is_empty() {
+            return default;
+        }
+        self.sections.iter().any(|it| {
+            let it = it.to_lowercase();
+            it == section || it == "all" || it == "everything" || (default && it == "default")
+        })
+    }
+}
+
+pub async fn info(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(state): Extension<ReplicationState>,
+    Extension(topology): Extension<SharedTopology>,
+    Extension(stats): Extension<SharedStats>,
+    Extension(clients): Extension<SharedClients>,
+    Extension(storage): Extension<SharedEngine>,
+    args: InfoArgs,
+) -> impl IntoResponse {
+    let mut output = String::new();
+
+    for &(section, default) in SECTIONS {
+        if !args.includes(section, default) {
+            continue;
+        }
+        // sections are separated by an empty line
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        match section {
+            "server" => server_info(&mut output, &config, &stats),
+            "clients" => clients_info(&mut output, &config, &clients),
+            "memory" => memory_info(&mut output),
+            "persistence" => persistence_info(&mut output, &storage),
+            "stats" => stats_info(&mut output, &stats, &clients, &storage),
+            "replication" => replication_info(&mut output, &state, &topology),
+            "cpu" => cpu_info(&mut output),
+            "commandstats" => stats.write_commandstats(&mut output),
+            "errorstats" => stats.write_errorstats(&mut output),
+            "keyspace" => keyspace_info(&mut output, &storage),
+            _ => unreachable!(),
+        }
+    }
+
+    Resp(Verbatim::txt(output))
+}
+
+fn server_info(output: &mut String, config: &Config, stats: &SharedStats) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let uptime = stats.started.elapsed().as_secs();
+    let executable = std::env::current_exe().unwrap_or_default();
+
+    writeln!(output, "# Server").unwrap();
+    writeln!(output, "redis_version:{REDIS_VERSION}").unwrap();
+    writeln!(output, "redis_git_sha1:00000000").unwrap();
+    writeln!(output, "redis_git_dirty:0").unwrap();
+    writeln!(output, "redis_mode:standalone").unwrap();
+    writeln!(output, "os:{}", os()).unwrap();
+    writeln!(output, "arch_bits:{}", usize::BITS).unwrap();
+    writeln!(output, "process_id:{}", std::process::id()).unwrap();
+    writeln!(output, "run_id:{}", stats.run_id).unwrap();
+    writeln!(output, "tcp_port:{}", config.port).unwrap();
+    writeln!(output, "server_time_usec:{}", now.as_micros()).unwrap();
+    writeln!(output, "uptime_in_seconds:{uptime}").unwrap();
+    writeln!(output, "uptime_in_days:{}", uptime / 86400).unwrap();
+    writeln!(output, "executable:{}", executable.display()).unwrap();
+    writeln!(output, "config_file:").unwrap();
+}
+
+/// Kernel name, release and machine, like `uname -srm`.
+fn os() -> String {
+    let read = |name| std::fs::read_to_string(format!("/proc/sys/kernel/{name}"));
+    match (read("ostype"), read("osrelease")) {
+        (Ok(kernel), Ok(release)) => format!(
+            "{} {} {}",
+            kernel.trim(),
+            release.trim(),
+            std::env::consts::ARCH
+        ),
+        _ => format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+    }
+}
+
+fn clients_info(output: &mut String, config: &Config, clients: &SharedClients) {
+    let (max_input, max_output) = clients
+        .list()
+        .iter()
+        .map(|it| it.buffers())
+        .fold((0, 0), |(input, output), (query, reply)| {
+            (input.max(query), output.max(reply))
+        });
+
+    writeln!(output, "# Clients").unwrap();
+    writeln!(output, "connected_clients:{}", clients.normal_len()).unwrap();
+    writeln!(output, "maxclients:{}", config.maxclients).unwrap();
+    writeln!(output, "client_recent_max_input_buffer:{max_input}").unwrap();
+    writeln!(output, "client_recent_max_output_buffer:{max_output}").unwrap();
+}
+
+fn memory_info(output: &mut String) {
+    let used = allocator::used();
+    let peak = allocator::peak();
+    let rss = allocator::resident().unwrap_or_default();
+    let total = allocator::system_total().unwrap_or_default();
+    let fragmentation = match used {
+        0 => 0.0,
+        used => rss as f64 / used as f64,
+    };
+
+    writeln!(output, "# Memory").unwrap();
+    writeln!(output, "used_memory:{used}").unwrap();
+    writeln!(output, "used_memory_human:{}", human_bytes(used)).unwrap();
+    writeln!(output, "used_memory_rss:{rss}").unwrap();
+    writeln!(output, "used_memory_rss_human:{}", human_bytes(rss)).unwrap();
+    writeln!(output, "used_memory_peak:{peak}").unwrap();
+    writeln!(output, "used_memory_peak_human:{}", human_bytes(peak)).unwrap();
+    writeln!(output, "total_system_memory:{total}").unwrap();
+    writeln!(output, "total_system_memory_human:{}", human_bytes(total)).unwrap();
+    writeln!(output, "maxmemory:0").unwrap();
+    writeln!(output, "maxmemory_human:0B").unwrap();
+    writeln!(output, "maxmemory_policy:noeviction").unwrap();
+    writeln!(output, "mem_fragmentation_ratio:{fragmentation:.2}").unwrap();
+    writeln!(output, "mem_allocator:libc").unwrap();
+}
+
+/// Sizes like `1.50M`, the way redis prints them.
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T", "P"];
+
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.2}{}", UNITS[unit])
+}
+
+fn persistence_info(output: &mut String, storage: &SharedEngine) {
+    let last_save = storage.last_save();
+    let status = if last_save.failed { "err" } else { "ok" };
+
+    writeln!(output, "# Persistence").unwrap();
+    writeln!(output, "loading:0").unwrap();
+    writeln!(output, "async_loading:0").unwrap();
+    writeln!(output, "rdb_bgsave_in_progress:0").unwrap();
+    let time = last_save
+        .time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    writeln!(output, "rdb_last_save_time:{}", time.as_secs()).unwrap();
+    writeln!(output, "rdb_last_bgsave_status:{status}").unwrap();
+    writeln!(output, "aof_enabled:0").unwrap();
+    writeln!(output, "aof_rewrite_in_progress:0").unwrap();
+}
+
+fn stats_info(
+    output: &mut String,
+    stats: &SharedStats,
+    clients: &SharedClients,
+    storage: &SharedEngine,
+) {
+    let connections = clients.stats();
+    let lookups = storage.lookups();
+
+    writeln!(output, "# Stats").unwrap();
+    writeln!(
+        output,
+        "total_connections_received:{}",
+        connections.connections_received
+    )
+    .unwrap();
+    writeln!(output, "total_commands_processed:{}", stats.total_calls()).unwrap();
+    writeln!(
+        output,
+        "total_net_input_bytes:{}",
+        connections.net_input_bytes
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "total_net_output_bytes:{}",
+        connections.net_output_bytes
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "rejected_connections:{}",
+        connections.rejected_connections
+    )
+    .unwrap();
+    writeln!(output, "evicted_keys:0").unwrap();
+    writeln!(output, "keyspace_hits:{}", lookups.hits).unwrap();
+    writeln!(output, "keyspace_misses:{}", lookups.misses).unwrap();
+    writeln!(output, "total_error_replies:{}", stats.total_errors()).unwrap();
+}
+
+fn replication_info(output: &mut String, state: &ReplicationState, topology: &Topology) {
+    writeln!(output, "# Replication").unwrap();
+    writeln!(output, "role:{}", state.role()).unwrap();
+
+    if let Some((master, link)) = topology.master_link() {
+        let status = if link.up { "up" } else { "down" };
+
+        writeln!(output, "master_host:{}", master.addr().ip()).unwrap();
+        writeln!(output, "master_port:{}", master.addr().port()).unwrap();
+        writeln!(output, "master_link_status:{status}").unwrap();
+        writeln!(
+            output,
+            "master_last_io_seconds_ago:{}",
+            link.last_io.elapsed().as_secs()
+        )
+        .unwrap();
+        writeln!(output, "master_sync_in_progress:0").unwrap();
+        writeln!(output, "slave_read_repl_offset:{}", state.read_offset()).unwrap();
+        writeln!(output, "slave_repl_offset:{}", state.offset()).unwrap();
+    }
+
+    let replicas = topology.replicas();
+    writeln!(output, "connected_slaves:{}", replicas.len()).unwrap();
+    for (i, replica) in replicas.iter().enumerate() {
+        writeln!(
+            output,
+            "slave{i}:ip={},port={},state={},offset={},lag={}",
+            replica.node.connection_addr().ip(),
+            replica.node.addr().port(),
+            replica.state,
+            replica.offset,
+            replica.lag(),
+        )
+        .unwrap();
+    }
+
+    writeln!(output, "master_replid:{}", state.id()).unwrap();
+    writeln!(output, "master_repl_offset:{}", state.offset()).unwrap();
+}
+
+fn cpu_info(output: &mut String) {
+    let [user, sys, children_user, children_sys] = cpu_times().unwrap_or_default();
+
+    writeln!(output, "# CPU").unwrap();
+    writeln!(output, "used_cpu_sys:{:.6}", sys.as_secs_f64()).unwrap();
+    writeln!(output, "used_cpu_user:{:.6}", user.as_secs_f64()).unwrap();
+    writeln!(
+        output,
+        "used_cpu_sys_children:{:.6}",
+        children_sys.as_secs_f64()
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "used_cpu_user_children:{:.6}",
+        children_user.as_secs_f64()
+    )
+    .unwrap();
+}
+
+/// User and system time of the process and of its children, only known on Linux.
+fn cpu_times() -> Option<[Duration; 4]> {
+    const TICKS_PER_SECOND: u64 = 100;
+
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // the name of the process is in parentheses and may contain spaces
+    let fields = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    // utime, stime, cutime and cstime are the 14th to 17th fields, after pid and name
+    let mut times = [Duration::ZERO; 4];
+    for (time, field) in times.iter_mut().zip(fields.get(11..15)?) {
+        let ticks: u64 = field.parse().ok()?;
+        *time = Duration::from_millis(ticks * 1000 / TICKS_PER_SECOND);
+    }
+    Some(times)
+}
+
+fn keyspace_info(output: &mut String, storage: &SharedEngine) {
+    let keyspace = storage.keyspace();
+
+    writeln!(output, "# Keyspace").unwrap();
+    if keyspace.keys > 0 {
+        writeln!(
+            output,
+            "db0:keys={},expires={},avg_ttl={}",
+            keyspace.keys, keyspace.expires, keyspace.avg_ttl
+        )
+        .unwrap();
+    }
+}
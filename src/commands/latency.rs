@@ -0,0 +1,56 @@
+use crate::{
+    args,
+    encoding::resp3::Verbatim,
+    error::RedisError,
+    latency::SharedLatencyMonitor,
+    request::{Arg, Extension},
+    response::{IntoResponse, Resp},
+};
+
+/// `LATENCY LATEST`
+pub async fn latest(Extension(monitor): Extension<SharedLatencyMonitor>) -> impl IntoResponse {
+    Resp(monitor.latest())
+}
+
+/// `LATENCY HISTORY event`
+pub async fn history(
+    Extension(monitor): Extension<SharedLatencyMonitor>,
+    Arg(event): Arg<1>,
+) -> impl IntoResponse {
+    Resp(monitor.history(&event.to_lowercase()))
+}
+
+args! {
+    /// `LATENCY RESET [event [event ...]]`, `LATENCY HISTOGRAM [command [command ...]]`
+    pub struct NamesArgs {
+        #[rest]
+        names: Vec<String>,
+    }
+}
+
+pub async fn reset(Extension(monitor): Extension<SharedLatencyMonitor>, args: NamesArgs) -> usize {
+    monitor.reset(&args.names)
+}
+
+/// `LATENCY GRAPH event`
+pub async fn graph(
+    Extension(monitor): Extension<SharedLatencyMonitor>,
+    Arg(event): Arg<1>,
+) -> Result<impl IntoResponse, RedisError> {
+    let graph = monitor
+        .graph(&event.to_lowercase())
+        .ok_or(RedisError::NoLatencySamples(event))?;
+    Ok(Resp(Verbatim::txt(graph)))
+}
+
+/// `LATENCY DOCTOR`
+pub async fn doctor(Extension(monitor): Extension<SharedLatencyMonitor>) -> impl IntoResponse {
+    Resp(Verbatim::txt(monitor.doctor()))
+}
+
+pub async fn histogram(
+    Extension(monitor): Extension<SharedLatencyMonitor>,
+    args: NamesArgs,
+) -> impl IntoResponse {
+    Resp(monitor.histograms(&args.names))
+}
@@ -1,23 +1,33 @@
 use std::{
+    collections::HashSet,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
-use bytes::BytesMut;
-use eyre::WrapErr;
-
 use crate::{
-    config::Config,
+    args,
+    clients::SharedClients,
+    config::{parse_loglevel, yes_no, Config},
+    encoding::resp3::Map,
     engine::SharedEngine,
     error::RedisError,
-    flag,
-    replication::ReplicationState,
-    request::{Arg, Extension},
-    response::{IntoResponse, Resp2},
+    latency::SharedLatencyMonitor,
+    logging::SharedLogging,
+    replication::master::WriteGuard,
+    request::{Arg, ArgParser, Extension, OneOf, Request},
+    response::{IntoResponse, Resp},
+    stats::SharedStats,
 };
 
+pub mod client;
+pub mod connection;
+pub mod info;
+pub mod latency;
 pub mod repl;
+pub mod server;
 pub mod stream;
+pub mod table;
 
 pub async fn ping() -> impl IntoResponse {
     "PONG"
@@ -35,74 +45,216 @@ pub async fn get(
     Ok(value)
 }
 
-flag!(Px, "px");
+/// Expiration of `SET`, relative in seconds or milliseconds, or at a unix time.
+pub enum Expiry {
+    Ex(u64),
+    Px(u64),
+    ExAt(u64),
+    PxAt(u64),
+}
+
+impl OneOf for Expiry {
+    const KEYWORDS: &'static [&'static str] = &["EX", "PX", "EXAT", "PXAT"];
+
+    fn parse(keyword: &str, parser: &mut ArgParser) -> Result<Self, RedisError> {
+        let value: u64 = parser.optional()?.ok_or(RedisError::Syntax)?;
+        if value == 0 {
+            return Err(RedisError::InvalidExpireTime("set".to_owned()));
+        }
+        Ok(match keyword {
+            "EX" => Self::Ex(value),
+            "PX" => Self::Px(value),
+            "EXAT" => Self::ExAt(value),
+            _ => Self::PxAt(value),
+        })
+    }
+}
+
+impl Expiry {
+    fn deadline(&self) -> SystemTime {
+        match *self {
+            Self::Ex(secs) => SystemTime::now() + Duration::from_secs(secs),
+            Self::Px(millis) => SystemTime::now() + Duration::from_millis(millis),
+            Self::ExAt(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            Self::PxAt(millis) => SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+        }
+    }
+}
+
+args! {
+    /// `SET key value [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds]`
+    pub struct SetArgs {
+        key: String,
+        value: String,
+        #[one_of]
+        expiry: Option<Expiry>,
+    }
+}
 
 pub async fn set(
+    _: WriteGuard,
     Extension(storage): Extension<SharedEngine>,
-    Arg(key): Arg<1>,
-    Arg(value): Arg<2>,
-    exp: Option<Px>,
+    args: SetArgs,
 ) -> Result<impl IntoResponse, RedisError> {
-    let eol = match exp {
-        Some(Px(v)) => {
-            let millis: u64 = v.parse().wrap_err("invalid px arg")?;
-            Some(SystemTime::now() + Duration::from_millis(millis))
-        }
-        _ => None,
-    };
+    let eol = args.expiry.map(|it| it.deadline());
 
-    storage.set(&key, value, eol).await?;
+    storage.set(&args.key, args.value, eol).await?;
     Ok("OK")
 }
 
-pub async fn info(
-    Extension(state): Extension<ReplicationState>,
-    _section: Option<Arg<1>>,
-) -> Result<impl IntoResponse, RedisError> {
-    use std::fmt::Write;
+/// `CONFIG GET parameter [parameter ...]`, unknown parameters are left out.
+pub async fn config_get(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(logging): Extension<SharedLogging>,
+    request: Request,
+) -> impl IntoResponse {
+    let values = request
+        .args
+        .into_iter()
+        .map(|it| it.to_lowercase())
+        .filter_map(|key| {
+            let value = match key.as_str() {
+                // the only parameter that changes at runtime
+                "loglevel" => Some(logging.level().to_string()),
+                _ => config_value(&config, &key),
+            };
+            value.map(|value| (key, value))
+        })
+        .collect();
 
-    let mut output = BytesMut::new();
-    writeln!(output, "# Replication").unwrap();
-    writeln!(output, "role:{}", state.role()).unwrap();
+    Resp(Map(values))
+}
 
-    writeln!(output, "master_replid:{}", state.id()).unwrap();
-    writeln!(output, "master_repl_offset:{}", state.offset()).unwrap();
+args! {
+    /// `CONFIG SET parameter value [parameter value ...]`
+    pub struct ConfigSetArgs {
+        #[pairs]
+        params: Vec<(String, String)>,
+    }
+}
 
-    Ok(output.freeze())
+/// `CONFIG SET`, only `loglevel` can be changed at runtime. Nothing is changed if any
+/// parameter is rejected.
+pub async fn config_set(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(logging): Extension<SharedLogging>,
+    args: ConfigSetArgs,
+) -> Result<&'static str, RedisError> {
+    let mut seen = HashSet::new();
+    let mut loglevel = None;
+
+    for (param, value) in args.params {
+        let param = param.to_lowercase();
+        if !seen.insert(param.clone()) {
+            return Err(RedisError::ConfigSetFailed {
+                param,
+                reason: "duplicate parameter".to_owned(),
+            });
+        }
+        match param.as_str() {
+            "loglevel" => match parse_loglevel(&value) {
+                Ok(level) => loglevel = Some(level),
+                Err(reason) => return Err(RedisError::ConfigSetFailed { param, reason }),
+            },
+            _ if config_value(&config, &param).is_some() => {
+                return Err(RedisError::ConfigSetFailed {
+                    param,
+                    reason: "can't set immutable config".to_owned(),
+                })
+            }
+            _ => return Err(RedisError::UnknownConfig(param)),
+        }
+    }
+
+    if let Some(level) = loglevel {
+        logging.set_level(level)?;
+    }
+    Ok("OK")
 }
 
-flag!(Get, "get");
+/// `CONFIG RESETSTAT`
+pub async fn config_resetstat(
+    Extension(stats): Extension<SharedStats>,
+    Extension(latency): Extension<SharedLatencyMonitor>,
+    Extension(clients): Extension<SharedClients>,
+    Extension(storage): Extension<SharedEngine>,
+) -> &'static str {
+    stats.reset();
+    latency.reset_histograms();
+    clients.reset_stats();
+    storage.reset_stats();
+    "OK"
+}
 
-pub async fn config(Extension(config): Extension<Arc<Config>>, Get(key): Get) -> impl IntoResponse {
-    match &*key {
-        "dir" => config
-            .dir
-            .as_ref()
-            .map(|it| Resp2(("dir", it.display().to_string()))),
-        "dbfilename" => config
-            .dbfilename
-            .clone()
-            .map(|it| Resp2(("dbfilename", it))),
+fn config_value(config: &Config, key: &str) -> Option<String> {
+    match key {
+        "port" => Some(config.port.to_string()),
+        "bind" => Some(
+            config
+                .bind
+                .iter()
+                .map(|it| it.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "unixsocket" => Some(path_or_empty(&config.unixsocket)),
+        "unixsocketperm" => Some(format!("{:o}", config.unixsocketperm.unwrap_or_default())),
+        "tls-port" => Some(config.tls_port.to_string()),
+        "logfile" => Some(path_or_empty(&config.logfile)),
+        "log-format" => Some(config.log_format.to_string()),
+        "metrics-port" => Some(config.metrics_port.to_string()),
+        "tls-cert-file" => Some(path_or_empty(&config.tls_cert_file)),
+        "tls-key-file" => Some(path_or_empty(&config.tls_key_file)),
+        "tls-ca-cert-file" => Some(path_or_empty(&config.tls_ca_cert_file)),
+        "tls-auth-clients" => Some(config.tls_auth_clients.to_string()),
+        "tls-replication" => Some(yes_no(config.tls_replication).to_owned()),
+        "timeout" => Some(config.timeout.as_secs().to_string()),
+        "tcp-keepalive" => Some(config.tcp_keepalive.as_secs().to_string()),
+        "maxclients" => Some(config.maxclients.to_string()),
+        "dir" => config.dir.as_ref().map(|it| it.display().to_string()),
+        "dbfilename" => config.dbfilename.clone(),
+        "repl-timeout" => Some(config.repl_timeout.as_secs().to_string()),
+        "min-replicas-to-write" => Some(config.min_replicas_to_write.to_string()),
+        "min-replicas-max-lag" => Some(config.min_replicas_max_lag.as_secs().to_string()),
+        "repl-diskless-sync" => Some(yes_no(config.repl_diskless_sync).to_owned()),
+        "client-query-buffer-limit" => Some(config.client_query_buffer_limit.to_string()),
+        "proto-max-bulk-len" => Some(config.proto_max_bulk_len.to_string()),
+        "client-output-buffer-limit" => Some(config.client_output_buffer_limit.to_string()),
+        "shutdown-timeout" => Some(config.shutdown_timeout.as_secs().to_string()),
+        "slowlog-log-slower-than" => Some(match config.slowlog_log_slower_than {
+            Some(threshold) => threshold.as_micros().to_string(),
+            None => "-1".to_owned(),
+        }),
+        "slowlog-max-len" => Some(config.slowlog_max_len.to_string()),
+        "latency-monitor-threshold" => {
+            Some(config.latency_monitor_threshold.as_millis().to_string())
+        }
         _ => None,
     }
 }
 
+fn path_or_empty(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|it| it.display().to_string())
+        .unwrap_or_default()
+}
+
 pub async fn keys(
     Extension(storage): Extension<SharedEngine>,
     Arg(pattern): Arg<1>,
 ) -> Result<impl IntoResponse, RedisError> {
     if pattern != "*" {
-        return Err(RedisError::Smth);
+        return Err(RedisError::Unsupported("pattern matching"));
     };
 
     let keys = storage.keys()?;
 
-    Ok(Resp2(keys))
+    Ok(Resp(keys))
 }
 
 pub async fn key_type(
     Extension(storage): Extension<SharedEngine>,
     Arg(key): Arg<1>,
 ) -> Result<impl IntoResponse, RedisError> {
-    Ok(Resp2(storage.get_type(&key)?.ok_or("none")))
+    Ok(Resp(storage.get_type(&key)?.ok_or("none")))
 }
@@ -9,17 +9,14 @@ use tracing::instrument;
 
 use crate::{
     error::RedisError,
-    network::NodeId,
-    replication::{
-        master::ReplicationWaitQueue, NodeRole, OffsetId, ReplicationState, SharedTopology,
-    },
+    network::{NodeId, PeerAddr},
+    replication::{master::ReplicationWaitQueue, ReplicationId, ReplicationState},
     request::{Arg, ArgParse, Extension},
     response::{IntoResponse, Response},
     state::ConnectionState,
 };
 
 pub async fn config(
-    Extension(topology): Extension<SharedTopology>,
     state: ConnectionState,
     Arg(key): Arg<1>,
     Arg(value): Arg<2>,
@@ -33,8 +30,11 @@ pub async fn config(
             let addr = addrs
                 .next()
                 .ok_or_else(|| eyre!("Can't resolve replica address"))?;
-            let id = NodeId::replica(addr).with_connection_addr(state.addr());
-            topology.add(id)?;
+            let PeerAddr::Tcp(connection_addr) = state.addr() else {
+                return Err(eyre!("replicas must connect over TCP or TLS").into());
+            };
+            // the replica joins the topology once `PSYNC` completes
+            let id = NodeId::replica(addr).with_connection_addr(connection_addr);
             state.set_node_id(id);
         }
         _ => {}
@@ -43,33 +43,49 @@ pub async fn config(
     Ok("OK")
 }
 
+/// Starts a full resynchronization. Replicas accept `PSYNC` too and serve
+/// the stream received from their own master.
+///
+/// `PSYNC ? -1` asks for a full resynchronization, otherwise the replica names the history
+/// it wants to continue. Partial resynchronization is not supported, so the replica
+/// always continues from the current offset after receiving a snapshot.
 #[instrument(err)]
 pub async fn psync(
     Extension(state): Extension<ReplicationState>,
-    Arg(_former_replication_id): Arg<1>,
-    Arg(offset_id): Arg<2>,
+    connection: ConnectionState,
+    Arg(replication_id): Arg<1>,
+    ArgParse(offset): ArgParse<i64, 2>,
 ) -> Result<impl IntoResponse, RedisError> {
-    if !matches!(state.role(), NodeRole::Master) {
-        return Err(RedisError::NotMaster);
+    // the replica is only known once it announced its port
+    if connection.node_id().is_none() {
+        return Err(RedisError::PsyncWithoutReplconf);
     }
-
-    let offset: i64 = offset_id
-        .parse()
-        .map_err(|_| eyre!("Failed to parse offset id"))?;
-    let offset: OffsetId = if offset == -1 { 0u64 } else { offset as u64 }.into();
-    if offset != state.offset() {
-        return Err(RedisError::Unhandled(eyre!(
-            "Replication from non-zero offset is not supported"
-        )));
+    if replication_id == "?" {
+        if offset != -1 {
+            return Err(RedisError::InvalidReplicationOffset(offset));
+        }
+    } else {
+        let id: ReplicationId = replication_id
+            .parse()
+            .map_err(|_| RedisError::InvalidReplicationId(replication_id.clone()))?;
+        let offset =
+            u64::try_from(offset).map_err(|_| RedisError::InvalidReplicationOffset(offset))?;
+        match id == state.id() {
+            true => {
+                tracing::info!(%offset, "Partial resynchronization is not supported, sending a snapshot")
+            }
+            false => tracing::info!(%id, "Unknown replication id, sending a snapshot"),
+        }
     }
 
     Ok(Response::Upgrade {
-        offset: offset.into(),
+        offset: state.offset(),
     })
 }
 
 pub async fn wait(
     Extension(wait_queue): Extension<ReplicationWaitQueue>,
+    connection: ConnectionState,
     ArgParse(count): ArgParse<usize, 1>,
     ArgParse(timeout): ArgParse<u64, 2>,
 ) -> Result<impl IntoResponse, RedisError> {
@@ -87,7 +103,8 @@ pub async fn wait(
 
     let _ = wait_queue.send((count, sync, not_interested)).await;
 
-    return receive
+    return connection
+        .blocked_on(receive)
         .await
         .map_err(|_| RedisError::Unhandled(eyre!("Receiver dropped")));
 }
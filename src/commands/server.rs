@@ -0,0 +1,295 @@
+use eyre::eyre;
+use serde::Serialize;
+
+use crate::{
+    args,
+    encoding::resp3::{Map, Set},
+    error::RedisError,
+    monitor::SharedMonitors,
+    request::{Extension, Request},
+    response::{IntoResponse, Resp, Response},
+    routing::{Command, CommandFlag, CommandTable, Keys},
+    shutdown::{ShutdownOptions, ShutdownQueue},
+    slowlog::SharedSlowLog,
+    state::ConnectionState,
+};
+
+args! {
+    /// `SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT]`
+    pub struct ShutdownArgs {
+        #[flag("NOSAVE")]
+        nosave: bool,
+        #[flag("SAVE")]
+        save: bool,
+        #[flag("NOW")]
+        now: bool,
+        #[flag("FORCE")]
+        force: bool,
+        #[flag("ABORT")]
+        abort: bool,
+    }
+}
+
+pub async fn shutdown(
+    Extension(queue): Extension<ShutdownQueue>,
+    args: ShutdownArgs,
+) -> Result<impl IntoResponse, RedisError> {
+    let options = ShutdownOptions {
+        save: match (args.nosave, args.save) {
+            (true, true) => return Err(RedisError::Syntax),
+            (true, false) => Some(false),
+            (false, true) => Some(true),
+            (false, false) => None,
+        },
+        now: args.now,
+        force: args.force,
+        abort: args.abort,
+    };
+    if options.abort && (options.save.is_some() || options.now || options.force) {
+        return Err(RedisError::Syntax);
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    queue
+        .send((options, tx))
+        .await
+        .map_err(|_| eyre!("Server is already shutting down"))?;
+
+    match rx.await {
+        Ok(Ok(())) => Ok("OK".into_response()),
+        Ok(Err(error)) => Err(error),
+        // the server is shutting down, the connection is closed without a reply
+        Err(_) => Ok(Response::Empty),
+    }
+}
+
+/// Reply of `COMMAND INFO`: name, arity, flags, first key, last key, key step,
+/// ACL categories, tips, key specifications and subcommands.
+#[derive(Serialize)]
+struct CommandInfo(
+    &'static str,
+    i32,
+    Set<&'static str>,
+    i32,
+    i32,
+    i32,
+    Set<&'static str>,
+    Vec<String>,
+    Vec<KeySpec>,
+    Vec<CommandInfo>,
+);
+
+#[derive(Serialize)]
+struct KeySpec {
+    flags: Set<&'static str>,
+    begin_search: BeginSearch,
+    find_keys: FindKeys,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", content = "spec", rename_all = "lowercase")]
+enum BeginSearch {
+    Index {
+        index: i32,
+    },
+    Keyword {
+        keyword: &'static str,
+        startfrom: i32,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", content = "spec", rename_all = "lowercase")]
+enum FindKeys {
+    Range {
+        lastkey: i32,
+        keystep: i32,
+        limit: i32,
+    },
+}
+
+impl CommandInfo {
+    fn new(command: &Command, commands: &CommandTable) -> Self {
+        let mut flags: Vec<&'static str> = command.flags.iter().map(|it| it.into()).collect();
+        let access = match command.has_flag(CommandFlag::Write) {
+            true => Set(vec!["RW", "update"]),
+            false => Set(vec!["RO", "access"]),
+        };
+
+        let (first, last, step, specs) = match command.keys {
+            Keys::None => (0, 0, 0, vec![]),
+            Keys::Range { first, last, step } => {
+                let spec = KeySpec {
+                    flags: access,
+                    begin_search: BeginSearch::Index { index: first },
+                    find_keys: FindKeys::Range {
+                        // relative to the first key, unless counted from the end
+                        lastkey: if last < 0 { last } else { last - first },
+                        keystep: step,
+                        limit: 0,
+                    },
+                };
+                (first, last, step, vec![spec])
+            }
+            Keys::Keyword { keyword } => {
+                flags.push("movablekeys");
+                let spec = KeySpec {
+                    flags: access,
+                    begin_search: BeginSearch::Keyword {
+                        keyword,
+                        startfrom: 1,
+                    },
+                    find_keys: FindKeys::Range {
+                        lastkey: -1,
+                        keystep: 1,
+                        limit: 2,
+                    },
+                };
+                (0, 0, 0, vec![spec])
+            }
+        };
+
+        Self(
+            command.name,
+            command.arity,
+            Set(flags),
+            first,
+            last,
+            step,
+            Set(command.categories.to_vec()),
+            vec![],
+            specs,
+            commands
+                .subcommands(command.name)
+                .map(|it| CommandInfo::new(it, commands))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct CommandDocs {
+    summary: &'static str,
+    since: &'static str,
+    group: &'static str,
+}
+
+/// `COMMAND [COUNT | INFO [name ...] | DOCS [name ...] | GETKEYS command [arg ...]]`
+pub async fn command(
+    Extension(commands): Extension<CommandTable>,
+    request: Request,
+) -> Result<Response, RedisError> {
+    let Some((subcommand, args)) = request.args.split_first() else {
+        let infos = commands
+            .iter()
+            .map(|it| CommandInfo::new(it, &commands))
+            .collect::<Vec<_>>();
+        return Ok(Resp(infos).into_response());
+    };
+
+    let response = match subcommand.to_lowercase().as_str() {
+        "count" if args.is_empty() => commands.len().into_response(),
+        "info" if args.is_empty() => {
+            let infos = commands
+                .iter()
+                .map(|it| CommandInfo::new(it, &commands))
+                .collect::<Vec<_>>();
+            Resp(infos).into_response()
+        }
+        "info" => {
+            let infos = args
+                .iter()
+                .map(|name| commands.get(name).map(|it| CommandInfo::new(it, &commands)))
+                .collect::<Vec<_>>();
+            Resp(infos).into_response()
+        }
+        "docs" => {
+            let docs = commands
+                .iter()
+                .filter(|it| {
+                    args.is_empty() || args.iter().any(|name| name.eq_ignore_ascii_case(it.name))
+                })
+                .map(|it| {
+                    let docs = CommandDocs {
+                        summary: it.summary,
+                        since: it.since,
+                        group: it.group,
+                    };
+                    (it.name, docs)
+                })
+                .collect();
+            Resp(Map(docs)).into_response()
+        }
+        "getkeys" if !args.is_empty() => {
+            let mut command = commands.get(&args[0]).ok_or(RedisError::InvalidCommand)?;
+            if let Some(subcommand) = args.get(1) {
+                let name = format!("{}|{}", command.name, subcommand);
+                command = commands.get(&name).unwrap_or(command);
+            }
+            if !command.check_arity(args.len()) {
+                return Err(RedisError::InvalidCommandArity);
+            }
+            let keys = command.keys(args);
+            if keys.is_empty() {
+                return Err(RedisError::NoKeyArguments);
+            }
+            Resp(keys).into_response()
+        }
+        "count" | "getkeys" => {
+            return Err(RedisError::WrongArity(format!(
+                "{}|{}",
+                request.command,
+                subcommand.to_lowercase()
+            )))
+        }
+        _ => {
+            return Err(RedisError::UnknownSubcommand {
+                command: request.command.to_uppercase(),
+                subcommand: subcommand.clone(),
+            })
+        }
+    };
+
+    Ok(response)
+}
+
+args! {
+    /// `SLOWLOG GET [count]`
+    pub struct SlowlogGetArgs {
+        #[optional]
+        count: Option<i64>,
+    }
+}
+
+pub async fn slowlog_get(
+    Extension(slowlog): Extension<SharedSlowLog>,
+    args: SlowlogGetArgs,
+) -> Result<impl IntoResponse, RedisError> {
+    let count = match args.count {
+        None => 10,
+        Some(-1) => usize::MAX,
+        Some(count) => usize::try_from(count).map_err(|_| RedisError::InvalidCount)?,
+    };
+
+    Ok(Resp(slowlog.get(count)))
+}
+
+/// `SLOWLOG LEN`
+pub async fn slowlog_len(Extension(slowlog): Extension<SharedSlowLog>) -> usize {
+    slowlog.len()
+}
+
+/// `SLOWLOG RESET`
+pub async fn slowlog_reset(Extension(slowlog): Extension<SharedSlowLog>) -> &'static str {
+    slowlog.reset();
+    "OK"
+}
+
+/// `MONITOR`
+pub async fn monitor(
+    Extension(monitors): Extension<SharedMonitors>,
+    state: ConnectionState,
+) -> Response {
+    state.set_monitor();
+    Response::Monitor(monitors.subscribe())
+}
@@ -1,12 +1,14 @@
 use std::{ops::Bound, time::Duration};
 
 use crate::{
+    args,
     commands::stream::parameters::{StreamRangeEnd, StreamRangeStart, StreamReadStart},
     engine::SharedEngine,
     error::RedisError,
-    flag,
-    request::{Arg, ArgParse, Extension, Request},
-    response::{IntoResponse, Resp2},
+    replication::master::WriteGuard,
+    request::{Arg, ArgParse, Extension},
+    response::{IntoResponse, Resp},
+    state::ConnectionState,
     value::{StreamId, StreamRange},
 };
 
@@ -14,7 +16,6 @@ mod parameters {
     use std::{ops::Bound, str::FromStr};
 
     use derive_more::{Display, From, Into};
-    use eyre::eyre;
 
     use crate::{
         error::RedisError,
@@ -36,7 +37,7 @@ mod parameters {
             if s.contains("-") {
                 return Ok(Self(s.parse()?));
             }
-            let ts = s.parse().map_err(|_| eyre!("Could not parse stream id"))?;
+            let ts = s.parse().map_err(|_| RedisError::InvalidStreamId)?;
 
             Ok(Self(StreamId::from((ts, 0))))
         }
@@ -57,7 +58,7 @@ mod parameters {
             if s.contains("-") {
                 return Ok(Self(s.parse()?));
             }
-            let ts = s.parse().map_err(|_| eyre!("Could not parse stream id"))?;
+            let ts = s.parse().map_err(|_| RedisError::InvalidStreamId)?;
 
             Ok(Self(StreamId::from((ts, u64::MAX))))
         }
@@ -94,26 +95,35 @@ mod parameters {
             if s.contains("-") {
                 return Ok(Self(s.parse()?));
             }
-            let ts = s.parse().map_err(|_| eyre!("Could not parse stream id"))?;
+            let ts = s.parse().map_err(|_| RedisError::InvalidStreamId)?;
 
             Ok(Self(StreamId::from((ts, u64::MIN))))
         }
     }
 }
 
+args! {
+    /// `XADD key id field value [field value ...]`
+    pub struct XAddArgs {
+        stream: String,
+        id: StreamId,
+        #[pairs]
+        fields: Vec<(String, String)>,
+    }
+}
+
 pub async fn xadd(
+    _: WriteGuard,
     Extension(engine): Extension<SharedEngine>,
-    Arg(stream): Arg<1>,
-    ArgParse(id): ArgParse<StreamId, 2>,
-    request: Request,
+    args: XAddArgs,
 ) -> Result<impl IntoResponse, RedisError> {
-    if request.args.len() % 2 != 0 {
-        return Err(RedisError::UnknownCommand);
-    }
-
-    let data = request.args.into_iter().skip(2).collect::<Vec<_>>();
+    let data = args
+        .fields
+        .into_iter()
+        .flat_map(|(field, value)| [field, value])
+        .collect::<Vec<_>>();
 
-    let id = engine.append(&stream, id, data)?;
+    let id = engine.append(&args.stream, args.id, data)?;
 
     Ok(id.to_string())
 }
@@ -126,38 +136,30 @@ pub async fn xrange(
 ) -> Result<impl IntoResponse, RedisError> {
     let data = engine.range(&stream, (start, end).into(), usize::MAX)?;
 
-    Ok(Resp2(data))
+    Ok(Resp(data))
 }
 
-flag!(Count, usize, "count");
-flag!(Block, usize, "block");
+args! {
+    /// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`
+    pub struct XReadArgs {
+        #[option("COUNT")]
+        count: Option<usize>,
+        #[option("BLOCK")]
+        block: Option<u64>,
+        #[split("STREAMS")]
+        streams: Vec<(String, StreamReadStart)>,
+    }
+}
 
 pub async fn xread(
     Extension(engine): Extension<SharedEngine>,
-    count: Option<Count>,
-    block: Option<Block>,
-    request: Request,
+    connection: ConnectionState,
+    args: XReadArgs,
 ) -> Result<impl IntoResponse, RedisError> {
-    let count = count.unwrap_or(Count(usize::MAX)).0;
+    let count = args.count.unwrap_or(usize::MAX);
 
     let mut output = vec![];
-    let Some(streams_pos) = request
-        .args
-        .iter()
-        .position(|it| it.eq_ignore_ascii_case("streams"))
-    else {
-        return Err(RedisError::UnknownCommand);
-    };
-
-    let args = &request.args[streams_pos + 1..];
-
-    if args.len() % 2 != 0 {
-        return Err(RedisError::UnknownCommand);
-    }
-
-    let (keys, ids) = args.split_at(args.len() / 2);
-    for (key, id) in keys.iter().zip(ids) {
-        let start: StreamReadStart = id.parse()?;
+    for (key, start) in &args.streams {
         if start.0 == StreamId::MAX {
             continue;
         }
@@ -173,22 +175,25 @@ pub async fn xread(
         output.push((key.to_owned(), values));
     }
 
-    match block {
-        Some(Block(timeout)) if output.is_empty() => {
-            let timeout = if timeout == 0 {
-                u64::MAX
-            } else {
-                timeout as u64
-            };
-            let Ok(_) =
-                tokio::time::timeout(Duration::from_millis(timeout), engine.wait().for_keys(keys))
-                    .await
+    match args.block {
+        Some(timeout) if output.is_empty() => {
+            let timeout = if timeout == 0 { u64::MAX } else { timeout };
+            let keys = args
+                .streams
+                .iter()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            let Ok(_) = connection
+                .blocked_on(tokio::time::timeout(
+                    Duration::from_millis(timeout),
+                    engine.wait().for_keys(&keys),
+                ))
+                .await
             else {
-                return Ok(Resp2(None));
+                return Ok(Resp(None));
             };
 
-            for (key, id) in keys.iter().zip(ids) {
-                let start: StreamReadStart = id.parse()?;
+            for (key, start) in &args.streams {
                 let values = engine.range(
                     key,
                     StreamRange(start.into_bound(), Bound::Unbounded),
@@ -203,5 +208,5 @@ pub async fn xread(
         _ => {}
     }
 
-    Ok(Resp2(Some(output)))
+    Ok(Resp(Some(output)))
 }
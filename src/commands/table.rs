@@ -0,0 +1,480 @@
+//! Metadata of every command, routes are registered with these.
+
+use crate::routing::{
+    Command,
+    CommandFlag::{self, *},
+    Keys,
+};
+
+const NO_FLAGS: &[CommandFlag] = &[];
+
+const fn single_key() -> Keys {
+    Keys::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    }
+}
+
+pub const PING: Command = Command {
+    name: "ping",
+    arity: -1,
+    flags: &[Fast],
+    keys: Keys::None,
+    categories: &["@fast", "@connection"],
+    group: "connection",
+    since: "1.0.0",
+    summary: "Returns the server's liveliness response.",
+};
+
+pub const ECHO: Command = Command {
+    name: "echo",
+    arity: 2,
+    flags: &[Fast],
+    keys: Keys::None,
+    categories: &["@fast", "@connection"],
+    group: "connection",
+    since: "1.0.0",
+    summary: "Returns the given string.",
+};
+
+pub const HELLO: Command = Command {
+    name: "hello",
+    arity: -1,
+    flags: &[Noscript, Fast],
+    keys: Keys::None,
+    categories: &["@fast", "@connection"],
+    group: "connection",
+    since: "6.0.0",
+    summary: "Handshakes with the Redis server.",
+};
+
+pub const CLIENT: Command = Command {
+    name: "client",
+    arity: -2,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow"],
+    group: "connection",
+    since: "2.4.0",
+    summary: "A container for client connection commands.",
+};
+
+pub const CLIENT_ID: Command = Command {
+    name: "client|id",
+    arity: 2,
+    flags: &[Noscript],
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "connection",
+    since: "5.0.0",
+    summary: "Returns the unique client ID of the connection.",
+};
+
+pub const CLIENT_INFO: Command = Command {
+    name: "client|info",
+    arity: 2,
+    flags: &[Noscript],
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "connection",
+    since: "6.2.0",
+    summary: "Returns information about the connection.",
+};
+
+pub const CLIENT_LIST: Command = Command {
+    name: "client|list",
+    arity: -2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous", "@connection"],
+    group: "connection",
+    since: "2.4.0",
+    summary: "Lists open connections.",
+};
+
+pub const CLIENT_GETNAME: Command = Command {
+    name: "client|getname",
+    arity: 2,
+    flags: &[Noscript],
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "connection",
+    since: "2.6.9",
+    summary: "Returns the name of the connection.",
+};
+
+pub const CLIENT_SETNAME: Command = Command {
+    name: "client|setname",
+    arity: 3,
+    flags: &[Noscript],
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "connection",
+    since: "2.6.9",
+    summary: "Sets the connection name.",
+};
+
+pub const CLIENT_KILL: Command = Command {
+    name: "client|kill",
+    arity: -3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous", "@connection"],
+    group: "connection",
+    since: "2.4.0",
+    summary: "Terminates open connections.",
+};
+
+pub const CLIENT_PAUSE: Command = Command {
+    name: "client|pause",
+    arity: -3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous", "@connection"],
+    group: "connection",
+    since: "3.0.0",
+    summary: "Suspends commands processing.",
+};
+
+pub const CLIENT_UNPAUSE: Command = Command {
+    name: "client|unpause",
+    arity: 2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous", "@connection"],
+    group: "connection",
+    since: "6.2.0",
+    summary: "Resumes processing commands from paused clients.",
+};
+
+pub const CLIENT_NO_EVICT: Command = Command {
+    name: "client|no-evict",
+    arity: 3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous", "@connection"],
+    group: "connection",
+    since: "7.0.0",
+    summary: "Sets the client eviction mode of the connection.",
+};
+
+pub const GET: Command = Command {
+    name: "get",
+    arity: 2,
+    flags: &[Readonly, Fast],
+    keys: single_key(),
+    categories: &["@read", "@string", "@fast"],
+    group: "string",
+    since: "1.0.0",
+    summary: "Returns the string value of a key.",
+};
+
+pub const SET: Command = Command {
+    name: "set",
+    arity: -3,
+    flags: &[Write, Denyoom],
+    keys: single_key(),
+    categories: &["@write", "@string", "@slow"],
+    group: "string",
+    since: "1.0.0",
+    summary:
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+};
+
+pub const KEYS: Command = Command {
+    name: "keys",
+    arity: 2,
+    flags: &[Readonly],
+    keys: Keys::None,
+    categories: &["@keyspace", "@read", "@slow", "@dangerous"],
+    group: "generic",
+    since: "1.0.0",
+    summary: "Returns all key names that match a pattern.",
+};
+
+pub const TYPE: Command = Command {
+    name: "type",
+    arity: 2,
+    flags: &[Readonly, Fast],
+    keys: single_key(),
+    categories: &["@keyspace", "@read", "@fast"],
+    group: "generic",
+    since: "1.0.0",
+    summary: "Determines the type of value stored at a key.",
+};
+
+pub const WAIT: Command = Command {
+    name: "wait",
+    arity: 3,
+    flags: &[Noscript],
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "generic",
+    since: "3.0.0",
+    summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+};
+
+pub const XADD: Command = Command {
+    name: "xadd",
+    arity: -5,
+    flags: &[Write, Denyoom, Fast],
+    keys: single_key(),
+    categories: &["@write", "@stream", "@fast"],
+    group: "stream",
+    since: "5.0.0",
+    summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+};
+
+pub const XRANGE: Command = Command {
+    name: "xrange",
+    arity: -4,
+    flags: &[Readonly],
+    keys: single_key(),
+    categories: &["@read", "@stream", "@slow"],
+    group: "stream",
+    since: "5.0.0",
+    summary: "Returns the messages from a stream within a range of IDs.",
+};
+
+pub const XREAD: Command = Command {
+    name: "xread",
+    arity: -4,
+    flags: &[Readonly, Blocking],
+    keys: Keys::Keyword { keyword: "streams" },
+    categories: &["@read", "@stream", "@slow", "@blocking"],
+    group: "stream",
+    since: "5.0.0",
+    summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.",
+};
+
+pub const INFO: Command = Command {
+    name: "info",
+    arity: -1,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow", "@dangerous"],
+    group: "server",
+    since: "1.0.0",
+    summary: "Returns information and statistics about the server.",
+};
+
+pub const CONFIG: Command = Command {
+    name: "config",
+    arity: -2,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow"],
+    group: "server",
+    since: "2.0.0",
+    summary: "A container for server configuration commands.",
+};
+
+pub const CONFIG_GET: Command = Command {
+    name: "config|get",
+    arity: -3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.0.0",
+    summary: "Returns the effective values of configuration parameters.",
+};
+
+pub const CONFIG_SET: Command = Command {
+    name: "config|set",
+    arity: -4,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.0.0",
+    summary: "Sets configuration parameters in-flight.",
+};
+
+pub const CONFIG_RESETSTAT: Command = Command {
+    name: "config|resetstat",
+    arity: 2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.0.0",
+    summary: "Resets the server's statistics.",
+};
+
+pub const COMMAND: Command = Command {
+    name: "command",
+    arity: -1,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow", "@connection"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Returns detailed information about all commands.",
+};
+
+pub const SLOWLOG: Command = Command {
+    name: "slowlog",
+    arity: -2,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow"],
+    group: "server",
+    since: "2.2.12",
+    summary: "A container for slow log commands.",
+};
+
+pub const SLOWLOG_GET: Command = Command {
+    name: "slowlog|get",
+    arity: -2,
+    flags: &[Admin],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.2.12",
+    summary: "Returns the slow log's entries.",
+};
+
+pub const SLOWLOG_LEN: Command = Command {
+    name: "slowlog|len",
+    arity: 2,
+    flags: &[Admin],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.2.12",
+    summary: "Returns the number of entries in the slow log.",
+};
+
+pub const SLOWLOG_RESET: Command = Command {
+    name: "slowlog|reset",
+    arity: 2,
+    flags: &[Admin],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.2.12",
+    summary: "Clears all entries from the slow log.",
+};
+
+pub const MONITOR: Command = Command {
+    name: "monitor",
+    arity: 1,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "1.0.0",
+    summary: "Listens for all requests received by the server in real-time.",
+};
+
+pub const LATENCY: Command = Command {
+    name: "latency",
+    arity: -2,
+    flags: NO_FLAGS,
+    keys: Keys::None,
+    categories: &["@slow"],
+    group: "server",
+    since: "2.8.13",
+    summary: "A container for latency diagnostics commands.",
+};
+
+pub const LATENCY_LATEST: Command = Command {
+    name: "latency|latest",
+    arity: 2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Returns the latest latency samples for all events.",
+};
+
+pub const LATENCY_HISTORY: Command = Command {
+    name: "latency|history",
+    arity: 3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Returns timestamp-latency samples for an event.",
+};
+
+pub const LATENCY_RESET: Command = Command {
+    name: "latency|reset",
+    arity: -2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Resets the latency data for one or more events.",
+};
+
+pub const LATENCY_GRAPH: Command = Command {
+    name: "latency|graph",
+    arity: 3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Returns a latency graph for an event.",
+};
+
+pub const LATENCY_DOCTOR: Command = Command {
+    name: "latency|doctor",
+    arity: 2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.13",
+    summary: "Returns a human-readable latency analysis report.",
+};
+
+pub const LATENCY_HISTOGRAM: Command = Command {
+    name: "latency|histogram",
+    arity: -2,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "7.0.0",
+    summary: "Returns the cumulative distribution of latencies of a subset or all commands.",
+};
+
+pub const SHUTDOWN: Command = Command {
+    name: "shutdown",
+    arity: -1,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "1.0.0",
+    summary: "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+};
+
+pub const REPLCONF: Command = Command {
+    name: "replconf",
+    arity: -1,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "3.0.0",
+    summary: "An internal command for configuring the replication stream.",
+};
+
+pub const PSYNC: Command = Command {
+    name: "psync",
+    arity: -3,
+    flags: &[Admin, Noscript],
+    keys: Keys::None,
+    categories: &["@admin", "@slow", "@dangerous"],
+    group: "server",
+    since: "2.8.0",
+    summary: "An internal command used in replication.",
+};
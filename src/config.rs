@@ -1,9 +1,65 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
+    pub port: u16,
+    /// Interfaces the TCP listeners are bound to.
+    pub bind: Vec<IpAddr>,
+    /// Path of the unix socket to listen on, besides TCP.
+    pub unixsocket: Option<PathBuf>,
+    /// Permissions of the unix socket file.
+    pub unixsocketperm: Option<u32>,
+    /// Port accepting TLS clients, `0` disables it.
+    pub tls_port: u16,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// CA certificates that client and master certificates are verified against.
+    pub tls_ca_cert_file: Option<PathBuf>,
+    pub tls_auth_clients: TlsAuthClients,
+    /// Connect to the master over TLS.
+    pub tls_replication: bool,
+    /// Clients idle for longer are disconnected, zero disables it.
+    pub timeout: Duration,
+    /// Interval of TCP keepalive probes, zero disables them.
+    pub tcp_keepalive: Duration,
+    /// Maximum number of connected clients, new connections above it are rejected.
+    pub maxclients: usize,
     pub dir: Option<PathBuf>,
     pub dbfilename: Option<String>,
+    /// Time after which a replica that doesn't acknowledge its offset is dropped.
+    pub repl_timeout: Duration,
+    /// Number of good replicas required to accept writes, `0` disables the check.
+    pub min_replicas_to_write: usize,
+    /// Maximum time since the last ack for a replica to be considered good.
+    pub min_replicas_max_lag: Duration,
+    /// Stream snapshots to replicas as they are generated instead of building them first.
+    pub repl_diskless_sync: bool,
+    /// Maximum size of unprocessed input buffered for a client before it's disconnected.
+    pub client_query_buffer_limit: usize,
+    /// Maximum length of a single bulk string in a request.
+    pub proto_max_bulk_len: usize,
+    /// Limits of unsent replies per client class, after which the client is disconnected.
+    pub client_output_buffer_limit: OutputBufferLimits,
+    /// How long shutdown waits for replicas to catch up and for connections to finish.
+    pub shutdown_timeout: Duration,
+    /// Commands running for longer are recorded in the slow log, `None` disables it.
+    pub slowlog_log_slower_than: Option<Duration>,
+    /// Number of entries kept in the slow log.
+    pub slowlog_max_len: usize,
+    /// Events lasting longer are recorded by the latency monitor, zero disables it.
+    pub latency_monitor_threshold: Duration,
+    /// Port serving Prometheus metrics over HTTP, `0` disables it.
+    pub metrics_port: u16,
+    /// Initial verbosity of the logs, it can be changed with `CONFIG SET loglevel`.
+    pub loglevel: LogLevel,
+    /// File the logs are appended to, standard output when `None`.
+    pub logfile: Option<PathBuf>,
+    pub log_format: LogFormat,
 }
 
 impl Config {
@@ -16,3 +72,261 @@ impl Config {
         }
     }
 }
+
+/// Parses `yes`/`no` values used by boolean redis options.
+pub fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("expected `yes` or `no`, got `{value}`")),
+    }
+}
+
+pub fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Parses a `bind` address, `*` and `::*` stand for every IPv4 and IPv6 interface.
+pub fn parse_bind_addr(value: &str) -> Result<IpAddr, String> {
+    match value {
+        "*" => Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        "::*" => Ok(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        _ => value
+            .parse()
+            .map_err(|_| format!("invalid bind address `{value}`")),
+    }
+}
+
+/// Parses file permissions written in octal, e.g. `700`.
+pub fn parse_permissions(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|it| *it <= 0o777)
+        .ok_or_else(|| format!("invalid permissions `{value}`"))
+}
+
+/// Whether TLS clients must present a certificate signed by the CA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TlsAuthClients {
+    #[default]
+    Yes,
+    No,
+    Optional,
+}
+
+impl fmt::Display for TlsAuthClients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yes => write!(f, "yes"),
+            Self::No => write!(f, "no"),
+            Self::Optional => write!(f, "optional"),
+        }
+    }
+}
+
+pub fn parse_tls_auth_clients(value: &str) -> Result<TlsAuthClients, String> {
+    match value.to_lowercase().as_str() {
+        "optional" => Ok(TlsAuthClients::Optional),
+        _ => parse_yes_no(value)
+            .map(|yes| {
+                if yes {
+                    TlsAuthClients::Yes
+                } else {
+                    TlsAuthClients::No
+                }
+            })
+            .map_err(|_| format!("expected `yes`, `no` or `optional`, got `{value}`")),
+    }
+}
+
+/// Verbosity of the logs, named like the values of redis `loglevel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    #[default]
+    Notice,
+    Warning,
+    Nothing,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Debug => write!(f, "debug"),
+            Self::Verbose => write!(f, "verbose"),
+            Self::Notice => write!(f, "notice"),
+            Self::Warning => write!(f, "warning"),
+            Self::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+pub fn parse_loglevel(value: &str) -> Result<LogLevel, String> {
+    match value.to_lowercase().as_str() {
+        "debug" => Ok(LogLevel::Debug),
+        "verbose" => Ok(LogLevel::Verbose),
+        "notice" => Ok(LogLevel::Notice),
+        "warning" => Ok(LogLevel::Warning),
+        "nothing" => Ok(LogLevel::Nothing),
+        _ => Err(
+            "argument(s) must be one of the following: debug, verbose, notice, warning, nothing"
+                .to_owned(),
+        ),
+    }
+}
+
+/// Format of the log lines, `json` writes one object per line for log collectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pretty => write!(f, "pretty"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+pub fn parse_log_format(value: &str) -> Result<LogFormat, String> {
+    match value.to_lowercase().as_str() {
+        "pretty" => Ok(LogFormat::Pretty),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(format!("expected `pretty` or `json`, got `{value}`")),
+    }
+}
+
+/// Parses memory sizes like `512mb` or `1gb`, the same units redis accepts in its config.
+pub fn parse_memory(value: &str) -> Result<usize, String> {
+    let lower = value.to_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid memory unit in `{value}`")),
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|it| it.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid memory size `{value}`"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientClass {
+    Normal,
+    Replica,
+}
+
+/// Client is disconnected when its output buffer reaches `hard` bytes, or stays above `soft`
+/// bytes for longer than `soft_seconds`. Zero disables the corresponding limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputBufferLimit {
+    pub hard: usize,
+    pub soft: usize,
+    pub soft_seconds: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputBufferLimits {
+    pub normal: OutputBufferLimit,
+    pub replica: OutputBufferLimit,
+}
+
+impl OutputBufferLimits {
+    pub fn get(&self, class: ClientClass) -> OutputBufferLimit {
+        match class {
+            ClientClass::Normal => self.normal,
+            ClientClass::Replica => self.replica,
+        }
+    }
+
+    pub fn set(&mut self, class: ClientClass, limit: OutputBufferLimit) {
+        match class {
+            ClientClass::Normal => self.normal = limit,
+            ClientClass::Replica => self.replica = limit,
+        }
+    }
+}
+
+impl Default for OutputBufferLimits {
+    fn default() -> Self {
+        const MB: usize = 1024 * 1024;
+
+        Self {
+            normal: OutputBufferLimit::default(),
+            replica: OutputBufferLimit {
+                hard: 256 * MB,
+                soft: 64 * MB,
+                soft_seconds: Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OutputBufferLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let classes = [("normal", self.normal), ("replica", self.replica)];
+        for (i, (class, limit)) in classes.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(
+                f,
+                "{class} {} {} {}",
+                limit.hard,
+                limit.soft,
+                limit.soft_seconds.as_secs()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `<class> <hard limit> <soft limit> <soft seconds>`, e.g. `replica 256mb 64mb 60`.
+pub fn parse_output_buffer_limit(value: &str) -> Result<(ClientClass, OutputBufferLimit), String> {
+    let [class, hard, soft, soft_seconds] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(format!(
+            "expected `<class> <hard limit> <soft limit> <soft seconds>`, got `{value}`"
+        ));
+    };
+
+    let class = match class.to_lowercase().as_str() {
+        "normal" => ClientClass::Normal,
+        "replica" | "slave" => ClientClass::Replica,
+        // there are no pub/sub clients to apply a limit to
+        "pubsub" => return Err("pub/sub clients aren't supported".to_owned()),
+        _ => return Err(format!("invalid client class `{class}`")),
+    };
+    let soft_seconds = soft_seconds
+        .parse()
+        .map_err(|_| format!("invalid soft seconds `{soft_seconds}`"))?;
+
+    Ok((
+        class,
+        OutputBufferLimit {
+            hard: parse_memory(hard)?,
+            soft: parse_memory(soft)?,
+            soft_seconds: Duration::from_secs(soft_seconds),
+        },
+    ))
+}
@@ -7,8 +7,8 @@ use thiserror::Error;
 pub enum Error {
     #[error("{0}")]
     Message(String),
-    #[error("TODO")]
-    SmthFailed,
+    #[error("invalid input")]
+    Malformed,
     #[error("Input contains extra characters that were not consumed")]
     TrailingCharacters,
     #[error("Expected array `*<length>`, got something else")]
@@ -17,6 +17,12 @@ pub enum Error {
     ExpectedString,
     #[error("Bad number")]
     ExpectedNumber,
+    #[error("Input ended before a complete value")]
+    Incomplete,
+    #[error("invalid bulk length")]
+    InvalidBulkLength,
+    #[error("invalid multibulk length")]
+    InvalidMultibulkLength,
 }
 
 impl ser::Error for Error {
@@ -35,7 +41,13 @@ impl de::Error for Error {
 }
 
 impl From<nom::Err<nom::error::Error<&[u8]>>> for Error {
-    fn from(_value: nom::Err<nom::error::Error<&[u8]>>) -> Self {
-        Self::SmthFailed
+    fn from(value: nom::Err<nom::error::Error<&[u8]>>) -> Self {
+        match value {
+            nom::Err::Incomplete(_) => Self::Incomplete,
+            nom::Err::Failure(e) if e.code == nom::error::ErrorKind::TooLarge => {
+                Self::InvalidBulkLength
+            }
+            nom::Err::Error(_) | nom::Err::Failure(_) => Self::Malformed,
+        }
     }
 }
@@ -0,0 +1,167 @@
+//! Inline commands are plain text lines, like `PING` typed into telnet.
+//! Arguments are separated by spaces and can be quoted the same way as in `redis-cli`.
+
+use super::Error;
+
+/// Inline requests longer than this are rejected, even if the query buffer limit is larger.
+const MAX_INLINE_SIZE: usize = 64 * 1024;
+
+/// Parses a single inline command, returning its arguments and the number of consumed bytes.
+/// An empty line yields no arguments.
+pub fn from_bytes(input: &[u8]) -> Result<(Vec<String>, usize), Error> {
+    let Some(end) = input.iter().position(|&it| it == b'\n') else {
+        if input.len() > MAX_INLINE_SIZE {
+            return Err(Error::Message("too big inline request".to_owned()));
+        }
+        return Err(Error::Incomplete);
+    };
+    let line = input[..end].strip_suffix(b"\r").unwrap_or(&input[..end]);
+
+    Ok((split_args(line)?, end + 1))
+}
+
+fn unbalanced() -> Error {
+    Error::Message("unbalanced quotes in request".to_owned())
+}
+
+fn split_args(line: &[u8]) -> Result<Vec<String>, Error> {
+    let mut args = vec![];
+    let mut rest = line;
+
+    loop {
+        let start = rest
+            .iter()
+            .position(|it| !it.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        rest = &rest[start..];
+        if rest.is_empty() {
+            return Ok(args);
+        }
+
+        let (arg, tail) = match rest[0] {
+            b'"' => double_quoted(&rest[1..])?,
+            b'\'' => single_quoted(&rest[1..])?,
+            _ => {
+                let end = rest
+                    .iter()
+                    .position(u8::is_ascii_whitespace)
+                    .unwrap_or(rest.len());
+                (rest[..end].to_vec(), &rest[end..])
+            }
+        };
+
+        args.push(String::from_utf8(arg).map_err(|_| Error::ExpectedString)?);
+        rest = tail;
+    }
+}
+
+/// Closing quote must be followed by a space or the end of the line.
+fn after_quote(rest: &[u8]) -> Result<&[u8], Error> {
+    match rest.first() {
+        None => Ok(rest),
+        Some(c) if c.is_ascii_whitespace() => Ok(rest),
+        Some(_) => Err(unbalanced()),
+    }
+}
+
+fn double_quoted(mut rest: &[u8]) -> Result<(Vec<u8>, &[u8]), Error> {
+    let mut arg = vec![];
+
+    loop {
+        match rest {
+            [] => return Err(unbalanced()),
+            [b'"', tail @ ..] => return Ok((arg, after_quote(tail)?)),
+            [b'\\', b'x', h, l, tail @ ..] if h.is_ascii_hexdigit() && l.is_ascii_hexdigit() => {
+                arg.push(hex_digit(*h) << 4 | hex_digit(*l));
+                rest = tail;
+            }
+            [b'\\', c, tail @ ..] => {
+                arg.push(match c {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    c => *c,
+                });
+                rest = tail;
+            }
+            [c, tail @ ..] => {
+                arg.push(*c);
+                rest = tail;
+            }
+        }
+    }
+}
+
+fn single_quoted(mut rest: &[u8]) -> Result<(Vec<u8>, &[u8]), Error> {
+    let mut arg = vec![];
+
+    loop {
+        match rest {
+            [] => return Err(unbalanced()),
+            [b'\'', tail @ ..] => return Ok((arg, after_quote(tail)?)),
+            [b'\\', b'\'', tail @ ..] => {
+                arg.push(b'\'');
+                rest = tail;
+            }
+            [c, tail @ ..] => {
+                arg.push(*c);
+                rest = tail;
+            }
+        }
+    }
+}
+
+fn hex_digit(c: u8) -> u8 {
+    (c as char).to_digit(16).expect("hex digit") as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &str) -> Vec<String> {
+        let (args, consumed) = from_bytes(input.as_bytes()).unwrap();
+        assert_eq!(consumed, input.len());
+        args
+    }
+
+    #[test]
+    fn arguments_are_split_on_spaces() {
+        assert_eq!(args("SET  key\tvalue\r\n"), ["SET", "key", "value"]);
+        assert_eq!(args("PING\n"), ["PING"]);
+        assert!(args("  \r\n").is_empty());
+    }
+
+    #[test]
+    fn only_the_first_line_is_consumed() {
+        let (args, consumed) = from_bytes(b"PING\r\nECHO a\r\n").unwrap();
+        assert_eq!(args, ["PING"]);
+        assert_eq!(consumed, 6);
+    }
+
+    #[test]
+    fn quotes_group_arguments_and_escape_characters() {
+        assert_eq!(args("SET \"a key\" 'it\\'s'\r\n"), ["SET", "a key", "it's"]);
+        assert_eq!(args("ECHO \"\\x41\\n\\\"\" ''\n"), ["ECHO", "A\n\"", ""]);
+        assert_eq!(args("ECHO 'no \\n escapes'\n"), ["ECHO", "no \\n escapes"]);
+    }
+
+    #[test]
+    fn unbalanced_quotes_fail() {
+        for input in ["ECHO \"open\r\n", "ECHO 'open\r\n", "ECHO \"a\"b\r\n"] {
+            assert!(
+                matches!(from_bytes(input.as_bytes()), Err(Error::Message(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn incomplete_lines_wait_for_more_input() {
+        assert!(matches!(from_bytes(b"SET key"), Err(Error::Incomplete)));
+        let long = vec![b'a'; MAX_INLINE_SIZE + 1];
+        assert!(matches!(from_bytes(&long), Err(Error::Message(_))));
+    }
+}
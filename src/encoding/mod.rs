@@ -1,5 +1,15 @@
 mod error;
+pub mod inline;
 pub mod rdb;
 pub mod resp2;
+pub mod resp3;
 
 pub use self::{error::Error, rdb::read_rdb_file};
+
+/// Protocol version negotiated by a client with `HELLO`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
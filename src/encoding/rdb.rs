@@ -1,9 +1,10 @@
 use std::time::SystemTime;
 
+use bytes::{Bytes, BytesMut};
 use eyre::eyre;
 use tracing::{instrument, Level};
 
-use crate::value::RedisValue;
+use crate::{error::RedisError, value::RedisValue};
 
 #[instrument(level = Level::DEBUG, skip(input), err)]
 pub fn read_rdb_file<'a>(
@@ -14,7 +15,7 @@ pub fn read_rdb_file<'a>(
 )> {
     let (mut input, aux_data) =
         parse::header(input).map_err(|_| eyre!("failed reading header of rdb file"))?;
-    tracing::info!(?aux_data, ?input, "Read header of RDB file");
+    tracing::info!(?aux_data, size = input.len(), "Read header of RDB file");
 
     let items_iter = std::iter::from_fn(move || {
         let Ok((rest, entry)) = parse::data_entry(input) else {
@@ -36,7 +37,7 @@ mod parse {
         error,
         error::ErrorKind,
         multi::{count, many0},
-        number::complete::{be_u64, le_u128, le_u32, le_u64, le_u8},
+        number::complete::{be_u32, be_u64, le_u128, le_u32, le_u64, le_u8},
         sequence::pair,
         IResult,
     };
@@ -207,13 +208,13 @@ mod parse {
                 Ok((rest, len))
             }
             0b10000000.. => {
-                let (input, len) = le_u32(rest)?;
+                let (input, len) = be_u32(rest)?;
                 Ok((input, len as usize))
             }
             0b01000000.. => {
-                let low = (first & !0b01_000_000) as usize;
-                let (input, high) = le_u8(rest)?;
-                let len = (high as usize) << 8 + low;
+                let high = (first & !0b01_000_000) as usize;
+                let (input, low) = le_u8(rest)?;
+                let len = (high << 8) | low as usize;
                 Ok((input, len))
             }
             _ => {
@@ -254,35 +255,211 @@ mod parse {
     }
 }
 
+/// Encodes entries into an RDB file, yielding it in chunks of roughly [`CHUNK_SIZE`]
+/// bytes, so neither the entries nor the file ever have to be kept in memory at once.
+/// Streams can't be encoded yet and are left out, while entries that failed to be read
+/// end it with an error.
+pub fn write_rdb(
+    entries: impl Iterator<Item = Result<(String, RedisValue, Option<SystemTime>), RedisError>>
+        + Send
+        + 'static,
+) -> impl Iterator<Item = Result<Bytes, RedisError>> + Send + 'static {
+    let mut entries = entries
+        .filter_map(|entry| match entry {
+            Ok((key, RedisValue::String(value), expiration)) => Some(Ok((key, value, expiration))),
+            Ok((key, _, _)) => {
+                tracing::warn!(%key, "Skipping stream that can't be written to an RDB snapshot");
+                None
+            }
+            Err(error) => Some(Err(error)),
+        })
+        .peekable();
+    let mut header = Some(encode::header(entries.peek().is_some()));
+    let mut finished = false;
+
+    std::iter::from_fn(move || {
+        if let Some(header) = header.take() {
+            return Some(Ok(header));
+        }
+        if finished {
+            return None;
+        }
+
+        let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+        while chunk.len() < CHUNK_SIZE {
+            let (key, value, expiration) = match entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(error)) => {
+                    finished = true;
+                    return Some(Err(error));
+                }
+                None => {
+                    encode::footer(&mut chunk);
+                    finished = true;
+                    break;
+                }
+            };
+            encode::entry(&mut chunk, &key, &value, expiration);
+        }
+
+        Some(Ok(chunk.freeze()))
+    })
+}
+
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
 mod encode {
-    use bytes::BufMut;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::value::ValueType;
+
+    pub fn header(has_entries: bool) -> Bytes {
+        let mut output = BytesMut::new();
+        output.put_slice(b"REDIS0011");
+        aux(&mut output, "redis-ver", "7.2.0");
+        aux(&mut output, "redis-bits", "64");
+        let ctime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        aux(&mut output, "ctime", &ctime.to_string());
+        aux(&mut output, "aof-base", "0");
+
+        if has_entries {
+            // select db 0
+            output.put_u8(0xFE);
+            length_encode(&mut output, 0);
+        }
+
+        output.freeze()
+    }
+
+    pub fn entry(output: &mut BytesMut, key: &str, value: &str, expiration: Option<SystemTime>) {
+        if let Some(expiration) = expiration {
+            let millis = expiration
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            output.put_u8(0xFC);
+            output.put_u64_le(millis as u64);
+        }
+        output.put_u8(ValueType::String.into_u8());
+        string(output, key);
+        string(output, value);
+    }
+
+    /// Writes end of file marker with zero checksum, which means it's not verified on load.
+    pub fn footer(output: &mut BytesMut) {
+        output.put_u8(0xFF);
+        output.put_u64_le(0);
+    }
+
+    fn aux(output: &mut impl BufMut, key: &str, value: &str) {
+        output.put_u8(0xFA);
+        string(output, key);
+        string(output, value);
+    }
+
+    fn string(output: &mut impl BufMut, value: &str) {
+        length_encode(output, value.len());
+        output.put_slice(value.as_bytes());
+    }
 
-    #[allow(dead_code)]
     fn length_encode(output: &mut impl BufMut, len: usize) {
-        const MAX: usize = 2 << 32 - 1;
         match len {
             0..=63 => {
                 output.put_u8(len as _);
             }
             64..=16383 => {
-                let [a, b, ..] = len.to_le_bytes();
-                let a = a | 0b01000000;
-                output.put_slice(&[a, b]);
+                output.put_u8(0b01000000 | (len >> 8) as u8);
+                output.put_u8(len as u8);
             }
-            16384..=MAX => {
+            _ => {
                 output.put_u8(0b10000000);
-                output.put_u32_le(len as u32);
+                output.put_u32(len as u32);
             }
-            _ => unimplemented!(),
         }
     }
 }
 
-pub static EMPTY: &'static [u8] = &[
-    0x52u8, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31, 0xfa, 0x09, 0x72, 0x65, 0x64, 0x69,
-    0x73, 0x2d, 0x76, 0x65, 0x72, 0x05, 0x37, 0x2e, 0x32, 0x2e, 0x30, 0xfa, 0x0a, 0x72, 0x65, 0x64,
-    0x69, 0x73, 0x2d, 0x62, 0x69, 0x74, 0x73, 0xc0, 0x40, 0xfa, 0x05, 0x63, 0x74, 0x69, 0x6d, 0x65,
-    0xc2, 0x6d, 0x08, 0xbc, 0x65, 0xfa, 0x08, 0x75, 0x73, 0x65, 0x64, 0x2d, 0x6d, 0x65, 0x6d, 0xc2,
-    0xb0, 0xc4, 0x10, 0x00, 0xfa, 0x08, 0x61, 0x6f, 0x66, 0x2d, 0x62, 0x61, 0x73, 0x65, 0xc0, 0x00,
-    0xff, 0xf0, 0x6e, 0x3b, 0xfe, 0xc0, 0xff, 0x5a, 0xa2,
-];
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::value::Stream;
+
+    fn write(
+        entries: Vec<(String, RedisValue, Option<SystemTime>)>,
+    ) -> Result<Vec<u8>, RedisError> {
+        let mut output = vec![];
+        for chunk in write_rdb(entries.into_iter().map(Ok)) {
+            output.extend_from_slice(&chunk?);
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn written_entries_are_read_back() {
+        let expiration = UNIX_EPOCH + Duration::from_millis(4_102_444_800_000);
+        // lengths that take one, two and five bytes to encode
+        let entries = vec![
+            ("short".to_owned(), "a".repeat(63), None),
+            ("medium".to_owned(), "b".repeat(16383), Some(expiration)),
+            ("long".to_owned(), "c".repeat(CHUNK_SIZE * 2), None),
+        ];
+        let rdb = write(
+            entries
+                .iter()
+                .map(|(key, value, exp)| (key.clone(), RedisValue::String(value.clone()), *exp))
+                .collect(),
+        )
+        .unwrap();
+
+        let (aux, read) = read_rdb_file(&rdb).unwrap();
+        assert!(aux.iter().any(|(key, _)| key == "redis-ver"));
+        let read = read
+            .map(|(key, value, exp)| {
+                let RedisValue::String(value) = value else {
+                    panic!("`{key}` isn't a string");
+                };
+                (key, value, exp)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(read, entries);
+    }
+
+    #[test]
+    fn empty_dataset_is_a_valid_file() {
+        let rdb = write(vec![]).unwrap();
+        let (_, read) = read_rdb_file(&rdb).unwrap();
+        assert_eq!(read.count(), 0);
+    }
+
+    #[test]
+    fn streams_are_skipped() {
+        let entries = vec![
+            ("a".to_owned(), RedisValue::String("1".to_owned()), None),
+            ("events".to_owned(), RedisValue::Stream(Stream::new()), None),
+        ];
+        let rdb = write(entries).unwrap();
+        let (_, read) = read_rdb_file(&rdb).unwrap();
+        assert_eq!(read.map(|(key, _, _)| key).collect::<Vec<_>>(), ["a"]);
+
+        let only_streams = vec![("events".to_owned(), RedisValue::Stream(Stream::new()), None)];
+        let rdb = write(only_streams).unwrap();
+        assert_eq!(read_rdb_file(&rdb).unwrap().1.count(), 0);
+    }
+
+    #[test]
+    fn read_errors_end_the_file_with_an_error() {
+        let entries = [
+            Ok(("a".to_owned(), RedisValue::String("1".to_owned()), None)),
+            Err(RedisError::Unhandled(eyre::eyre!("storage is broken"))),
+        ];
+        let chunks = write_rdb(entries.into_iter()).collect::<Vec<_>>();
+        assert!(chunks.last().unwrap().is_err());
+    }
+}
@@ -13,6 +13,7 @@ use super::Error;
 
 pub struct Deserializer<'de> {
     input: &'de [u8],
+    max_bulk_len: usize,
 }
 
 impl<'de> Deserializer<'de> {
@@ -23,7 +24,16 @@ impl<'de> Deserializer<'de> {
         self.input.is_empty()
     }
     pub fn new(input: &'de [u8]) -> Self {
-        Self { input }
+        Self {
+            input,
+            max_bulk_len: usize::MAX,
+        }
+    }
+
+    /// Rejects bulk strings longer than `max_bulk_len` as soon as their header is read.
+    pub fn with_max_bulk_len(mut self, max_bulk_len: usize) -> Self {
+        self.max_bulk_len = max_bulk_len;
+        self
     }
 
     fn peek(&self) -> Option<u8> {
@@ -48,7 +58,7 @@ impl<'de> Deserializer<'de> {
     }
 
     fn get_bytes(&mut self) -> Result<Vec<u8>, Error> {
-        let (rest, bytes) = parse::bytes(self.input)?;
+        let (rest, bytes) = parse::bytes(self.input, self.max_bulk_len)?;
         self.input = rest;
         self.sep()?;
 
@@ -56,7 +66,7 @@ impl<'de> Deserializer<'de> {
     }
 
     fn get_any_string(&mut self) -> Result<String, Error> {
-        let (rest, string) = parse::any_string(self.input)?;
+        let (rest, string) = parse::any_string(self.input, self.max_bulk_len)?;
         self.input = rest;
         self.sep()?;
         Ok(string)
@@ -66,54 +76,63 @@ impl<'de> Deserializer<'de> {
 mod parse {
     use nom::{
         branch::alt,
-        bytes::complete::{tag, take, take_until, take_while},
+        bytes::streaming::{tag, take, take_until, take_while},
         character::is_digit,
+        combinator::opt,
         error,
         error::ErrorKind,
         IResult,
     };
 
+    // Parsers are streaming, so input that ends too early is reported as `Incomplete`
+    // and can be distinguished from malformed input.
+
     pub fn number(input: &[u8]) -> IResult<&[u8], i64> {
-        let (rest, u) = take_while(is_digit)(input)?;
-        let u = std::str::from_utf8(u).expect("valid utf8");
-        let u: i64 = u
-            .parse()
-            .map_err(|_| nom::Err::Error(error::Error::new(input, ErrorKind::Digit)))?;
-        Ok((rest, u))
+        let (rest, sign) = opt(tag("-"))(input)?;
+        let (rest, u) = take_while(is_digit)(rest)?;
+        let u: i64 = std::str::from_utf8(u)
+            .ok()
+            .and_then(|it| it.parse().ok())
+            .ok_or_else(|| nom::Err::Error(error::Error::new(input, ErrorKind::Digit)))?;
+        Ok((rest, if sign.is_some() { -u } else { u }))
     }
 
-    pub fn any_string(input: &[u8]) -> IResult<&[u8], String> {
-        alt((simple_string, byte_string))(input)
+    pub fn any_string(input: &[u8], max_len: usize) -> IResult<&[u8], String> {
+        alt((simple_string, |input| byte_string(input, max_len)))(input)
     }
 
-    pub fn bytes(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    pub fn bytes(input: &[u8], max_len: usize) -> IResult<&[u8], &[u8]> {
         let (input, _) = tag("$")(input)?;
         let (input, len) = number(input)?;
         if len == -1 {
             return Ok((input, &[]));
         }
+        if len < -1 || len as u64 > max_len as u64 {
+            return Err(nom::Err::Failure(error::Error::new(
+                input,
+                ErrorKind::TooLarge,
+            )));
+        }
         let (input, _) = separator(input)?;
         let (input, b) = take(len as usize)(input)?;
         Ok((input, b))
     }
 
-    pub fn byte_string(input: &[u8]) -> IResult<&[u8], String> {
-        let (input, bytes) = bytes(input)?;
-
-        if bytes.len() == 0 {
-            return Ok((input, String::new()));
-        }
+    pub fn byte_string(input: &[u8], max_len: usize) -> IResult<&[u8], String> {
+        let (rest, bytes) = bytes(input, max_len)?;
+        let s = std::str::from_utf8(bytes)
+            .map_err(|_| nom::Err::Failure(error::Error::new(input, ErrorKind::Char)))?;
 
-        let s = std::str::from_utf8(bytes).expect("valid utf8");
-        Ok((input, s.to_owned()))
+        Ok((rest, s.to_owned()))
     }
 
     pub fn simple_string(input: &[u8]) -> IResult<&[u8], String> {
         let (input, _) = tag("+")(input)?;
-        let (input, b) = take_until("\r\n")(input)?;
-        let s = std::str::from_utf8(b).expect("valid utf8");
+        let (rest, b) = take_until("\r\n")(input)?;
+        let s = std::str::from_utf8(b)
+            .map_err(|_| nom::Err::Failure(error::Error::new(input, ErrorKind::Char)))?;
 
-        Ok((input, s.to_owned()))
+        Ok((rest, s.to_owned()))
     }
 
     pub fn separator(input: &[u8]) -> IResult<&[u8], ()> {
@@ -286,14 +305,19 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if self.peek() == Some(b'*') {
-            let _ = self.consume();
-            let length = self.get_integer()? as _;
-            let value = visitor.visit_seq(Array(length, self))?;
-
-            Ok(value)
-        } else {
-            Err(Error::ExpectedArray)
+        match self.peek() {
+            Some(b'*') => {
+                let _ = self.consume();
+                let length = self.get_integer()?;
+                if !(0..=i32::MAX as i64).contains(&length) {
+                    return Err(Error::InvalidMultibulkLength);
+                }
+                let value = visitor.visit_seq(Array(length as _, self))?;
+
+                Ok(value)
+            }
+            None => Err(Error::Incomplete),
+            Some(_) => Err(Error::ExpectedArray),
         }
     }
 
@@ -10,7 +10,15 @@ pub fn from_bytes<T>(input: &[u8]) -> Result<(T, usize), Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut deserializer = de::Deserializer::new(input);
+    from_bytes_with_limit(input, usize::MAX)
+}
+
+/// Same as [`from_bytes`], but fails on bulk strings longer than `max_bulk_len`.
+pub fn from_bytes_with_limit<T>(input: &[u8], max_bulk_len: usize) -> Result<(T, usize), Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let mut deserializer = de::Deserializer::new(input).with_max_bulk_len(max_bulk_len);
     let t = T::deserialize(&mut deserializer)?;
 
     Ok((t, input.len() - deserializer.left()))
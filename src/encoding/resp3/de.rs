@@ -0,0 +1,200 @@
+use bytes::Bytes;
+use serde::{
+    de::{self, value::MapDeserializer, value::SeqDeserializer, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+};
+
+use super::{Error, Value};
+
+/// Parses a single value from the start of the input, returning it with the number
+/// of consumed bytes. Fails with [`Error::Incomplete`] if the input ends too early.
+pub fn parse(input: &[u8]) -> Result<(Value, usize), Error> {
+    let mut parser = Parser { input, pos: 0 };
+    let value = parser.value()?;
+    Ok((value, parser.pos))
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, Error> {
+        let Some(&kind) = self.input.get(self.pos) else {
+            return Err(Error::Incomplete);
+        };
+        self.pos += 1;
+
+        Ok(match kind {
+            b'+' => Value::SimpleString(self.string()?),
+            b'-' => Value::Error(self.string()?),
+            b':' => Value::Integer(self.number()?),
+            b'$' => match self.number()? {
+                -1 => Value::Null,
+                len => Value::BulkString(Bytes::copy_from_slice(self.bulk(len)?)),
+            },
+            b'!' => {
+                let len = self.number()?;
+                Value::Error(utf8(self.bulk(len)?)?)
+            }
+            b'=' => {
+                let len = self.number()?;
+                let data = utf8(self.bulk(len)?)?;
+                let (format, text) = data
+                    .split_once(':')
+                    .ok_or_else(|| Error::Message("Invalid verbatim string".to_owned()))?;
+                Value::Verbatim {
+                    format: format.to_owned(),
+                    text: text.to_owned(),
+                }
+            }
+            b'*' => match self.number()? {
+                -1 => Value::Null,
+                len => Value::Array(self.items(len)?),
+            },
+            b'~' => {
+                let len = self.number()?;
+                Value::Set(self.items(len)?)
+            }
+            b'>' => {
+                let len = self.number()?;
+                Value::Push(self.items(len)?)
+            }
+            b'%' => {
+                let len = self.number()?;
+                Value::Map(self.pairs(len)?)
+            }
+            b'|' => {
+                let len = self.number()?;
+                let attributes = self.pairs(len)?;
+                Value::WithAttributes(attributes, Box::new(self.value()?))
+            }
+            b'_' => {
+                self.line()?;
+                Value::Null
+            }
+            b'#' => match self.line()? {
+                b"t" => Value::Boolean(true),
+                b"f" => Value::Boolean(false),
+                _ => return Err(Error::Message("Invalid boolean".to_owned())),
+            },
+            b',' => {
+                let line = utf8(self.line()?)?;
+                let double = match line.as_str() {
+                    "inf" => f64::INFINITY,
+                    "-inf" => f64::NEG_INFINITY,
+                    v => v.parse().map_err(|_| Error::ExpectedNumber)?,
+                };
+                Value::Double(double)
+            }
+            b'(' => Value::BigNumber(self.string()?),
+            _ => return Err(Error::Message(format!("Unknown type `{}`", kind as char))),
+        })
+    }
+
+    fn line(&mut self) -> Result<&'a [u8], Error> {
+        let rest = &self.input[self.pos..];
+        let end = rest
+            .windows(2)
+            .position(|it| it == b"\r\n")
+            .ok_or(Error::Incomplete)?;
+        self.pos += end + 2;
+        Ok(&rest[..end])
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        utf8(self.line()?)
+    }
+
+    fn number(&mut self) -> Result<i64, Error> {
+        let line = self.line()?;
+        std::str::from_utf8(line)
+            .ok()
+            .and_then(|it| it.parse().ok())
+            .ok_or(Error::ExpectedNumber)
+    }
+
+    fn bulk(&mut self, len: i64) -> Result<&'a [u8], Error> {
+        let len = usize::try_from(len).map_err(|_| Error::ExpectedNumber)?;
+        let rest = &self.input[self.pos..];
+        if rest.len() < len + 2 {
+            return Err(Error::Incomplete);
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(Error::TrailingCharacters);
+        }
+        self.pos += len + 2;
+        Ok(&rest[..len])
+    }
+
+    fn items(&mut self, len: i64) -> Result<Vec<Value>, Error> {
+        let len = usize::try_from(len).map_err(|_| Error::ExpectedNumber)?;
+        (0..len).map(|_| self.value()).collect()
+    }
+
+    fn pairs(&mut self, len: i64) -> Result<Vec<(Value, Value)>, Error> {
+        let len = usize::try_from(len).map_err(|_| Error::ExpectedNumber)?;
+        (0..len)
+            .map(|_| Ok((self.value()?, self.value()?)))
+            .collect()
+    }
+}
+
+fn utf8(data: &[u8]) -> Result<String, Error> {
+    String::from_utf8(data.to_vec()).map_err(|_| Error::ExpectedString)
+}
+
+/// Deserializes typed values out of a parsed [`Value`].
+pub struct Deserializer(pub Value);
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::SimpleString(s) | Value::BigNumber(s) => visitor.visit_string(s),
+            Value::Verbatim { text, .. } => visitor.visit_string(text),
+            Value::Error(e) => Err(Error::Message(e)),
+            Value::Integer(n) => visitor.visit_i64(n),
+            Value::BulkString(b) => visitor.visit_byte_buf(b.to_vec()),
+            Value::Array(items) | Value::Set(items) | Value::Push(items) => {
+                visitor.visit_seq(SeqDeserializer::new(items.into_iter()))
+            }
+            Value::Null => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Double(d) => visitor.visit_f64(d),
+            Value::Map(pairs) => visitor.visit_map(MapDeserializer::new(pairs.into_iter())),
+            Value::WithAttributes(_, value) => Deserializer(*value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(Deserializer(value)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer(self)
+    }
+}
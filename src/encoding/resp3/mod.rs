@@ -0,0 +1,321 @@
+mod de;
+mod ser;
+mod value;
+
+use bytes::Bytes;
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+
+pub use self::value::Value;
+pub use crate::encoding::Error;
+use crate::encoding::Protocol;
+
+const SET_TOKEN: &str = "$resp3::Set";
+const PUSH_TOKEN: &str = "$resp3::Push";
+const BIG_NUMBER_TOKEN: &str = "$resp3::BigNumber";
+const VERBATIM_TOKEN: &str = "$resp3::Verbatim";
+const ATTRIBUTES_TOKEN: &str = "$resp3::Attributes";
+
+// the server only reads RESP2 requests, parsing is for peers that reply in RESP3
+#[allow(dead_code)]
+pub fn from_bytes<T>(input: &[u8]) -> Result<(T, usize), Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let (value, consumed) = de::parse(input)?;
+    Ok((T::deserialize(de::Deserializer(value))?, consumed))
+}
+
+pub fn to_value<T>(value: &T) -> Result<Value, Error>
+where
+    T: Serialize,
+{
+    value.serialize(ser::Serializer)
+}
+
+#[allow(dead_code)]
+pub fn to_bytes<T>(value: &T) -> Result<Bytes, Error>
+where
+    T: Serialize,
+{
+    Ok(to_value(value)?.encode(Protocol::Resp3))
+}
+
+/// Unordered collection, sent as an array over RESP2.
+#[derive(Debug, Clone)]
+pub struct Set<T>(pub Vec<T>);
+
+impl<T: Serialize> Serialize for Set<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SET_TOKEN, &self.0)
+    }
+}
+
+/// Out of band data, sent as an array over RESP2.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Push<T>(pub Vec<T>);
+
+impl<T: Serialize> Serialize for Push<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(PUSH_TOKEN, &self.0)
+    }
+}
+
+/// Integer that doesn't fit into `i64`, sent as a bulk string over RESP2.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct BigNumber(pub String);
+
+impl Serialize for BigNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(BIG_NUMBER_TOKEN, &self.0)
+    }
+}
+
+/// String with a three letter format hint, sent as a bulk string over RESP2.
+#[derive(Debug, Clone)]
+pub struct Verbatim {
+    pub format: &'static str,
+    pub text: String,
+}
+
+impl Verbatim {
+    pub fn txt(text: impl Into<String>) -> Self {
+        Self {
+            format: "txt",
+            text: text.into(),
+        }
+    }
+}
+
+impl Serialize for Verbatim {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(VERBATIM_TOKEN, &(self.format, &self.text))
+    }
+}
+
+/// Reply preceded by attributes, which are omitted over RESP2.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct WithAttributes<A, T> {
+    pub attributes: Map<String, A>,
+    pub value: T,
+}
+
+impl<A: Serialize, T: Serialize> Serialize for WithAttributes<A, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(ATTRIBUTES_TOKEN, &(&self.attributes, &self.value))
+    }
+}
+
+/// Map that keeps insertion order, sent as a flat array of pairs over RESP2.
+#[derive(Debug, Clone)]
+pub struct Map<K, V>(pub Vec<(K, V)>);
+
+impl<K: Serialize, V: Serialize> Serialize for Map<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use serde_bytes::ByteBuf;
+
+    use super::*;
+    use crate::encoding::Protocol;
+
+    /// Wire shape of the value in RESP3 and in RESP2.
+    fn encoded<T: Serialize>(value: T) -> (String, String) {
+        let value = to_value(&value).unwrap();
+        let encode = |protocol| String::from_utf8(value.encode(protocol).to_vec()).unwrap();
+        (encode(Protocol::Resp3), encode(Protocol::Resp2))
+    }
+
+    fn same(encoded: &str) -> (String, String) {
+        (encoded.to_owned(), encoded.to_owned())
+    }
+
+    #[test]
+    fn types_shared_with_resp2_are_unchanged() {
+        assert_eq!(encoded("OK"), same("+OK\r\n"));
+        assert_eq!(encoded(-42), same(":-42\r\n"));
+        assert_eq!(encoded(ByteBuf::from("a\r\nb")), same("$4\r\na\r\nb\r\n"));
+        assert_eq!(encoded(("a", 1)), same("*2\r\n+a\r\n:1\r\n"));
+        let error = Value::Error("ERR boom".to_owned());
+        assert_eq!(error.encode(Protocol::Resp3), Bytes::from("-ERR boom\r\n"));
+        assert_eq!(error.encode(Protocol::Resp2), Bytes::from("-ERR boom\r\n"));
+    }
+
+    #[test]
+    fn null_is_a_null_bulk_string_in_resp2() {
+        assert_eq!(
+            encoded(None::<i64>),
+            ("_\r\n".to_owned(), "$-1\r\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn boolean_is_an_integer_in_resp2() {
+        assert_eq!(encoded(true), ("#t\r\n".to_owned(), ":1\r\n".to_owned()));
+        assert_eq!(encoded(false), ("#f\r\n".to_owned(), ":0\r\n".to_owned()));
+    }
+
+    #[test]
+    fn double_is_a_bulk_string_in_resp2() {
+        assert_eq!(
+            encoded(1.5),
+            (",1.5\r\n".to_owned(), "$3\r\n1.5\r\n".to_owned())
+        );
+        assert_eq!(
+            encoded(f64::NEG_INFINITY),
+            (",-inf\r\n".to_owned(), "$4\r\n-inf\r\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn big_number_is_a_bulk_string_in_resp2() {
+        assert_eq!(
+            encoded(u64::MAX),
+            (
+                "(18446744073709551615\r\n".to_owned(),
+                "$20\r\n18446744073709551615\r\n".to_owned()
+            )
+        );
+        assert_eq!(encoded(i64::MAX as u64), same(":9223372036854775807\r\n"));
+    }
+
+    #[test]
+    fn verbatim_string_loses_its_format_in_resp2() {
+        assert_eq!(
+            encoded(Verbatim::txt("a\nb")),
+            ("=7\r\ntxt:a\nb\r\n".to_owned(), "$3\r\na\nb\r\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn map_is_a_flat_array_of_pairs_in_resp2() {
+        assert_eq!(
+            encoded(Map(vec![("a", 1), ("b", 2)])),
+            (
+                "%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n".to_owned(),
+                "*4\r\n+a\r\n:1\r\n+b\r\n:2\r\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn set_is_an_array_in_resp2() {
+        assert_eq!(
+            encoded(Set(vec!["a", "b"])),
+            (
+                "~2\r\n+a\r\n+b\r\n".to_owned(),
+                "*2\r\n+a\r\n+b\r\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn push_is_an_array_in_resp2() {
+        assert_eq!(
+            encoded(Push(vec!["message", "news"])),
+            (
+                ">2\r\n+message\r\n+news\r\n".to_owned(),
+                "*2\r\n+message\r\n+news\r\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn attributes_are_omitted_in_resp2() {
+        let value = WithAttributes {
+            attributes: Map(vec![("ttl".to_owned(), 10)]),
+            value: "OK",
+        };
+        assert_eq!(
+            encoded(value),
+            (
+                "|1\r\n+ttl\r\n:10\r\n+OK\r\n".to_owned(),
+                "+OK\r\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn every_type_round_trips() {
+        let values = [
+            Value::SimpleString("OK".to_owned()),
+            Value::Error("ERR boom".to_owned()),
+            Value::Integer(-42),
+            Value::BulkString(Bytes::from("a\r\nb")),
+            Value::Array(vec![Value::Integer(1), Value::Null]),
+            Value::Null,
+            Value::Boolean(true),
+            Value::Double(-1.5),
+            Value::Double(f64::INFINITY),
+            Value::BigNumber("18446744073709551616".to_owned()),
+            Value::Verbatim {
+                format: "txt".to_owned(),
+                text: "a:b".to_owned(),
+            },
+            Value::Map(vec![(
+                Value::SimpleString("a".to_owned()),
+                Value::Integer(1),
+            )]),
+            Value::Set(vec![Value::Boolean(false)]),
+            Value::Push(vec![Value::SimpleString("message".to_owned())]),
+            Value::WithAttributes(
+                vec![(Value::SimpleString("ttl".to_owned()), Value::Integer(10))],
+                Box::new(Value::SimpleString("OK".to_owned())),
+            ),
+        ];
+
+        for value in values {
+            let encoded = value.encode(Protocol::Resp3);
+            let (parsed, consumed) = de::parse(&encoded).unwrap();
+            assert_eq!(parsed, value);
+            assert_eq!(consumed, encoded.len());
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_incomplete() {
+        let encoded = to_bytes(&Map(vec![("a", 1)])).unwrap();
+        for end in 0..encoded.len() {
+            assert!(matches!(de::parse(&encoded[..end]), Err(Error::Incomplete)));
+        }
+    }
+
+    #[test]
+    fn typed_values_are_deserialized() {
+        let encoded = to_bytes(&WithAttributes {
+            attributes: Map(vec![("ttl".to_owned(), 10)]),
+            value: Map(vec![("a", Set(vec![1.5]))]),
+        })
+        .unwrap();
+        let (value, _) = from_bytes::<HashMap<String, Vec<f64>>>(&encoded).unwrap();
+        assert_eq!(value, HashMap::from([("a".to_owned(), vec![1.5])]));
+
+        let encoded = to_bytes(&(Push(vec!["news"]), BigNumber("1".repeat(30)), true)).unwrap();
+        let (value, _) = from_bytes::<(Vec<String>, String, bool)>(&encoded).unwrap();
+        assert_eq!(value, (vec!["news".to_owned()], "1".repeat(30), true));
+    }
+
+    #[test]
+    fn nested_values_are_downgraded() {
+        assert_eq!(
+            encoded(Map(vec![("flags", Set(vec![true]))])),
+            (
+                "%1\r\n+flags\r\n~1\r\n#t\r\n".to_owned(),
+                "*2\r\n+flags\r\n*1\r\n:1\r\n".to_owned()
+            )
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::encoding::Protocol;
+
+/// Reply in its most specific RESP3 shape. Encoding it with [`Protocol::Resp2`]
+/// downgrades types that RESP2 doesn't have, the same way redis does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Bytes),
+    Array(Vec<Value>),
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(String),
+    Verbatim {
+        format: String,
+        text: String,
+    },
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    Push(Vec<Value>),
+    /// Attributes are auxiliary data sent before the actual reply.
+    WithAttributes(Vec<(Value, Value)>, Box<Value>),
+}
+
+impl Value {
+    pub fn encode(&self, protocol: Protocol) -> Bytes {
+        let mut output = BytesMut::new();
+        self.write(&mut output, protocol);
+        output.freeze()
+    }
+
+    pub fn write(&self, output: &mut BytesMut, protocol: Protocol) {
+        match (self, protocol) {
+            (Self::SimpleString(s), _) => write!(output, "+{s}\r\n").unwrap(),
+            (Self::Error(e), _) => write!(output, "-{e}\r\n").unwrap(),
+            (Self::Integer(n), _) => write!(output, ":{n}\r\n").unwrap(),
+            (Self::BulkString(b), _) => write_bulk(output, '$', b),
+            (Self::Array(items), _) => write_aggregate(output, '*', items, protocol),
+            (Self::Null, Protocol::Resp2) => output.put_slice(b"$-1\r\n"),
+            (Self::Null, Protocol::Resp3) => output.put_slice(b"_\r\n"),
+            (Self::Boolean(b), Protocol::Resp2) => write!(output, ":{}\r\n", *b as i64).unwrap(),
+            (Self::Boolean(b), Protocol::Resp3) => {
+                write!(output, "#{}\r\n", if *b { 't' } else { 'f' }).unwrap()
+            }
+            (Self::Double(d), Protocol::Resp2) => write_bulk(output, '$', format_double(*d)),
+            (Self::Double(d), Protocol::Resp3) => {
+                write!(output, ",{}\r\n", format_double(*d)).unwrap()
+            }
+            (Self::BigNumber(n), Protocol::Resp2) => write_bulk(output, '$', n),
+            (Self::BigNumber(n), Protocol::Resp3) => write!(output, "({n}\r\n").unwrap(),
+            (Self::Verbatim { text, .. }, Protocol::Resp2) => write_bulk(output, '$', text),
+            (Self::Verbatim { format, text }, Protocol::Resp3) => {
+                write_bulk(output, '=', format!("{format}:{text}"))
+            }
+            (Self::Map(pairs), Protocol::Resp2) => {
+                write!(output, "*{}\r\n", pairs.len() * 2).unwrap();
+                write_pairs(output, pairs, protocol);
+            }
+            (Self::Map(pairs), Protocol::Resp3) => {
+                write!(output, "%{}\r\n", pairs.len()).unwrap();
+                write_pairs(output, pairs, protocol);
+            }
+            (Self::Set(items), Protocol::Resp2) => write_aggregate(output, '*', items, protocol),
+            (Self::Set(items), Protocol::Resp3) => write_aggregate(output, '~', items, protocol),
+            (Self::Push(items), Protocol::Resp2) => write_aggregate(output, '*', items, protocol),
+            (Self::Push(items), Protocol::Resp3) => write_aggregate(output, '>', items, protocol),
+            (Self::WithAttributes(_, value), Protocol::Resp2) => value.write(output, protocol),
+            (Self::WithAttributes(attributes, value), Protocol::Resp3) => {
+                write!(output, "|{}\r\n", attributes.len()).unwrap();
+                write_pairs(output, attributes, protocol);
+                value.write(output, protocol);
+            }
+        }
+    }
+}
+
+fn write_bulk(output: &mut BytesMut, prefix: char, data: impl AsRef<[u8]>) {
+    let data = data.as_ref();
+    write!(output, "{prefix}{}\r\n", data.len()).unwrap();
+    output.put_slice(data);
+    output.put_slice(b"\r\n");
+}
+
+fn write_aggregate(output: &mut BytesMut, prefix: char, items: &[Value], protocol: Protocol) {
+    write!(output, "{prefix}{}\r\n", items.len()).unwrap();
+    for item in items {
+        item.write(output, protocol);
+    }
+}
+
+fn write_pairs(output: &mut BytesMut, pairs: &[(Value, Value)], protocol: Protocol) {
+    for (key, value) in pairs {
+        key.write(output, protocol);
+        value.write(output, protocol);
+    }
+}
+
+fn format_double(value: f64) -> String {
+    match value {
+        f64::INFINITY => "inf".to_owned(),
+        f64::NEG_INFINITY => "-inf".to_owned(),
+        v if v.is_nan() => "nan".to_owned(),
+        v => v.to_string(),
+    }
+}
@@ -1,13 +1,15 @@
-use std::{sync::Arc, time::SystemTime};
+use std::{path::Path, sync::Arc, time::SystemTime};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures_util::stream::BoxStream;
 use tokio::sync::mpsc;
 
 use crate::{
     config::Config,
     engine::wait::WaitBuilder,
     error::RedisError,
+    latency::SharedLatencyMonitor,
     replication::master::ReplicationCommand,
     storage,
     value::{StreamId, StreamRange, ValueType},
@@ -42,24 +44,68 @@ pub trait Engine {
         eol: Option<SystemTime>,
     ) -> Result<(), RedisError>;
     fn wait(&self) -> WaitBuilder;
-    fn dump(&self) -> Bytes;
+    /// Serializes current state into RDB, failing on values that can't be serialized.
+    fn dump(&self) -> Result<Bytes, RedisError>;
+    /// Serializes current state into RDB, producing it in chunks as it's generated.
+    fn dump_stream(&self) -> BoxStream<'static, Result<Bytes, RedisError>>;
+    /// Writes a snapshot into the file, replacing it only once the snapshot is complete.
+    async fn save(&self, path: &Path) -> Result<(), RedisError>;
+    /// Time of the last snapshot saved or loaded, and whether the last save succeeded.
+    fn last_save(&self) -> LastSave;
+    fn keyspace(&self) -> Keyspace;
+    fn lookups(&self) -> Lookups;
+    /// Resets the hits and misses of the keyspace, for `CONFIG RESETSTAT`.
+    fn reset_stats(&self);
+}
+
+/// Size of the keyspace, reported by `INFO keyspace`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keyspace {
+    pub keys: usize,
+    /// Keys with an expiration.
+    pub expires: usize,
+    /// Average time to live of the keys with an expiration, in milliseconds.
+    pub avg_ttl: u64,
+}
+
+/// Lookups of keys, reported by `INFO stats`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lookups {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Snapshots written to disk, reported by `INFO persistence`.
+#[derive(Debug, Clone, Copy)]
+pub struct LastSave {
+    /// Time of the last snapshot saved, or of the one loaded at startup, otherwise the time
+    /// the server started like in redis.
+    pub time: SystemTime,
+    /// Whether the last attempt to save a snapshot failed.
+    pub failed: bool,
 }
 
 pub type SharedEngine = Arc<dyn Engine + Send + Sync + 'static>;
 
 pub fn create_engine(
     config: &Config,
+    latency: SharedLatencyMonitor,
 ) -> eyre::Result<(SharedEngine, mpsc::Receiver<ReplicationCommand>)> {
     let (tx, rx) = mpsc::channel(128);
 
     let memstore = storage::Memory::default();
 
     Ok(match config.db_file() {
-        None => (Arc::new(RedisEngine::new(memstore, tx)), rx),
+        None => (Arc::new(RedisEngine::new(memstore, tx, latency, None)), rx),
 
         Some(db) => {
+            // the snapshot is created when missing, so it's only loaded if it existed before
+            let loaded = std::fs::metadata(&db).and_then(|it| it.modified()).ok();
             let persisted = storage::Persisted::new(memstore, db)?;
-            (Arc::new(RedisEngine::new(persisted, tx)), rx)
+            (
+                Arc::new(RedisEngine::new(persisted, tx, latency, loaded)),
+                rx,
+            )
         }
     })
 }
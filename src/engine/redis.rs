@@ -1,38 +1,115 @@
-use std::time::SystemTime;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use async_trait::async_trait;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use eyre::eyre;
+use futures_util::{stream::BoxStream, StreamExt};
 use parking_lot::Mutex;
 use tokio::sync::broadcast;
 
 use crate::{
     encoding::rdb,
-    engine::{wait::WaitBuilder, Engine},
+    engine::{wait::WaitBuilder, Engine, Keyspace, LastSave, Lookups},
     error::RedisError,
+    latency::{self, SharedLatencyMonitor},
     replication::master::{ReplicationCommand, ReplicationCommandQueue},
     storage::Storage,
     value::{RedisValue, Stream, StreamId, StreamRange, ValueType},
 };
 
+/// Number of entries copied at once while serializing a snapshot.
+const SNAPSHOT_BATCH: usize = 128;
+
 pub struct RedisEngine<S: Storage> {
-    storage: Mutex<S>,
+    storage: Arc<Mutex<S>>,
     replication_queue: ReplicationCommandQueue,
     updates: broadcast::Sender<String>,
+    latency: SharedLatencyMonitor,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    last_save: Mutex<LastSave>,
 }
 
 impl<S: Storage> RedisEngine<S> {
-    pub fn new(storage: S, replication_queue: ReplicationCommandQueue) -> Self {
+    pub fn new(
+        storage: S,
+        replication_queue: ReplicationCommandQueue,
+        latency: SharedLatencyMonitor,
+        loaded: Option<SystemTime>,
+    ) -> Self {
         Self {
-            storage: Mutex::new(storage),
+            storage: Arc::new(Mutex::new(storage)),
             replication_queue,
             updates: broadcast::channel(128).0,
+            latency,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            last_save: Mutex::new(LastSave {
+                time: loaded.unwrap_or_else(SystemTime::now),
+                failed: false,
+            }),
         }
     }
 }
 
+impl<S: Storage> RedisEngine<S> {
+    /// Entries in key order, copied in small batches so the lock is only held briefly while
+    /// a snapshot is serialized. Writes made in the meantime may or may not be included,
+    /// which is fine since replicas apply them again from the replication stream.
+    /// A storage error ends it after being yielded.
+    fn entries(
+        storage: Arc<Mutex<S>>,
+    ) -> impl Iterator<Item = Result<(String, RedisValue, Option<SystemTime>), RedisError>>
+           + Send
+           + 'static
+    where
+        S: 'static,
+    {
+        let mut batch = Vec::new().into_iter();
+        let mut last: Option<String> = None;
+        let mut exhausted = false;
+
+        std::iter::from_fn(move || loop {
+            if let Some(entry) = batch.next() {
+                return Some(Ok(entry));
+            }
+            if exhausted {
+                return None;
+            }
+
+            let mut storage = storage.lock();
+            let entries = match storage.entries_after(last.as_deref()) {
+                Ok(entries) => entries
+                    .into_iter()
+                    .take(SNAPSHOT_BATCH)
+                    .map(|(k, v, exp)| (k.to_owned(), v.clone(), exp))
+                    .collect::<Vec<_>>(),
+                Err(error) => {
+                    exhausted = true;
+                    return Some(Err(error.into()));
+                }
+            };
+            exhausted = entries.len() < SNAPSHOT_BATCH;
+            last = entries.last().map(|(key, _, _)| key.clone());
+            batch = entries.into_iter();
+        })
+    }
+
+    fn record_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 #[async_trait]
-impl<S: Storage> Engine for RedisEngine<S> {
+impl<S: Storage + 'static> Engine for RedisEngine<S> {
     fn keys(&self) -> Result<Vec<String>, RedisError> {
         Ok(self
             .storage
@@ -44,12 +121,14 @@ impl<S: Storage> Engine for RedisEngine<S> {
     }
 
     fn get(&self, key: &str) -> Result<Option<String>, RedisError> {
-        let Some(data) = self.storage.lock().get(key)? else {
+        let data = self.storage.lock().get(key)?;
+        self.record_lookup(data.is_some());
+        let Some(data) = data else {
             return Ok(None);
         };
 
         let RedisValue::String(value) = data else {
-            return Err(RedisError::InvalidType("string"));
+            return Err(RedisError::WrongType);
         };
 
         Ok(Some(value))
@@ -69,7 +148,7 @@ impl<S: Storage> Engine for RedisEngine<S> {
         let RedisValue::Stream(s) =
             storage.get_or_insert(stream, || RedisValue::Stream(Stream::new()))?
         else {
-            return Err(RedisError::InvalidType("stream"));
+            return Err(RedisError::WrongType);
         };
 
         let key = s.append(key, value)?;
@@ -87,7 +166,9 @@ impl<S: Storage> Engine for RedisEngine<S> {
         count: usize,
     ) -> Result<Vec<(StreamId, Vec<String>)>, RedisError> {
         let mut storage = self.storage.lock();
-        let Some(RedisValue::Stream(stream)) = storage.get_mut(stream)? else {
+        let value = storage.get_mut(stream)?;
+        self.record_lookup(value.is_some());
+        let Some(RedisValue::Stream(stream)) = value else {
             return Ok(vec![]);
         };
         let values = stream
@@ -127,7 +208,101 @@ impl<S: Storage> Engine for RedisEngine<S> {
         WaitBuilder::new(self.updates.subscribe())
     }
 
-    fn dump(&self) -> Bytes {
-        Bytes::from_static(rdb::EMPTY)
+    fn dump(&self) -> Result<Bytes, RedisError> {
+        self.latency.measure(latency::SNAPSHOT, || {
+            let mut output = BytesMut::new();
+            for chunk in rdb::write_rdb(Self::entries(self.storage.clone())) {
+                output.extend_from_slice(&chunk?);
+            }
+            Ok(output.freeze())
+        })
+    }
+
+    fn dump_stream(&self) -> BoxStream<'static, Result<Bytes, RedisError>> {
+        let mut chunks = rdb::write_rdb(Self::entries(self.storage.clone()));
+        // only the time spent serializing counts, not the time waiting for the replicas to read
+        let mut latency = Some(self.latency.clone());
+        let mut spent = Duration::ZERO;
+        let timed = std::iter::from_fn(move || {
+            let start = Instant::now();
+            let chunk = chunks.next();
+            spent += start.elapsed();
+            if chunk.is_none() {
+                if let Some(latency) = latency.take() {
+                    latency.record(latency::SNAPSHOT, spent);
+                }
+            }
+            chunk
+        });
+        futures_util::stream::iter(timed).boxed()
+    }
+
+    async fn save(&self, path: &Path) -> Result<(), RedisError> {
+        let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+        let result = async {
+            tokio::fs::write(&temp, self.dump()?).await?;
+            tokio::fs::rename(&temp, path).await?;
+            Ok::<_, RedisError>(())
+        }
+        .await;
+
+        let mut last_save = self.last_save.lock();
+        last_save.failed = result.is_err();
+        if result.is_ok() {
+            last_save.time = SystemTime::now();
+        }
+        result
+    }
+
+    fn last_save(&self) -> LastSave {
+        *self.last_save.lock()
+    }
+
+    fn keyspace(&self) -> Keyspace {
+        let storage = self.storage.lock();
+        let (expires, average) = storage.expirations();
+        Keyspace {
+            keys: storage.key_count(),
+            expires,
+            avg_ttl: average
+                .and_then(|it| it.duration_since(SystemTime::now()).ok())
+                .map_or(0, |it| it.as_millis() as u64),
+        }
+    }
+
+    fn lookups(&self) -> Lookups {
+        Lookups {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset_stats(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Memory;
+
+    #[test]
+    fn entries_are_copied_in_batches_in_key_order() {
+        let mut memory = Memory::default();
+        let count = SNAPSHOT_BATCH * 2 + 1;
+        for i in 0..count {
+            let value = RedisValue::String(i.to_string());
+            memory.set(&format!("key:{i:04}"), value, None).unwrap();
+        }
+
+        let keys = RedisEngine::entries(Arc::new(Mutex::new(memory)))
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>();
+        let expected = (0..count)
+            .map(|i| format!("key:{i:04}"))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
     }
 }
@@ -3,16 +3,28 @@ use thiserror::Error;
 
 use crate::routing::Response;
 
+/// Errors replied to clients as `-<code> <message>`, with messages matching redis.
 #[derive(Debug, Error)]
 pub enum RedisError {
     #[error("Error during IO")]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     SerializationError(#[from] crate::encoding::Error),
-    #[error("Something happened")]
-    Smth,
-    #[error("Unknown command")]
-    UnknownCommand,
+
+    #[error("unknown command '{command:.128}', with args beginning with: {}", quoted(.args))]
+    UnknownCommand { command: String, args: Vec<String> },
+
+    #[error("unknown subcommand '{subcommand}'. Try {command} HELP.")]
+    UnknownSubcommand { command: String, subcommand: String },
+
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+
+    #[error("syntax error")]
+    Syntax,
+
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
 
     #[error(transparent)]
     Unhandled(#[from] eyre::Report),
@@ -20,18 +32,195 @@ pub enum RedisError {
     #[error("Failed to receive response")]
     ResponseFailed,
 
-    #[error("Request can't be processed by replica node")]
+    #[error("You can't write against a read only replica.")]
     NotMaster,
 
-    #[error("Expected to receive a number")]
+    #[error("Not enough good replicas to write.")]
+    NoReplicas,
+
+    #[error("unsupported protocol version")]
+    NoProto,
+
+    #[error("invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("value is not an integer or out of range")]
     ExpectedNumber(#[from] std::num::ParseIntError),
 
-    #[error("Expected type `{0}`")]
-    InvalidType(&'static str),
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+
+    #[error("The ID specified in XADD must be greater than 0-0")]
+    StreamIdZero,
+
+    #[error("The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+
+    #[error("Errors trying to SHUTDOWN. Check logs.")]
+    ShutdownFailed,
+
+    #[error("No shutdown in progress.")]
+    NoShutdown,
+
+    #[error("Client names cannot contain spaces, newlines or special characters.")]
+    InvalidClientName,
+
+    #[error("Invalid command specified")]
+    InvalidCommand,
+
+    #[error("Invalid number of arguments specified for command")]
+    InvalidCommandArity,
+
+    #[error("The command has no key arguments")]
+    NoKeyArguments,
+
+    #[error("max number of clients reached")]
+    MaxClients,
+
+    #[error("No such client")]
+    NoSuchClient,
+
+    #[error("Unknown client type '{0}'")]
+    UnknownClientType(String),
+
+    #[error("timeout is not an integer or out of range")]
+    InvalidTimeout,
+
+    #[error("No samples available for event '{0}'")]
+    NoLatencySamples(String),
+
+    #[error("count should be greater than or equal to -1")]
+    InvalidCount,
+
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+
+    #[error(
+        "Unbalanced '{0}' list of streams: for each stream key an ID or '$' must be specified."
+    )]
+    UnbalancedStreams(String),
+
+    #[error("PSYNC without REPLCONF listening-port")]
+    PsyncWithoutReplconf,
+
+    #[error("invalid replication id '{0}'")]
+    InvalidReplicationId(String),
+
+    #[error("invalid replication offset '{0}'")]
+    InvalidReplicationOffset(i64),
+
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownConfig(String),
+
+    #[error("CONFIG SET failed (possibly related to argument '{param}') - {reason}")]
+    ConfigSetFailed { param: String, reason: String },
+}
+
+/// Parsing arguments as `String` never fails.
+impl From<std::convert::Infallible> for RedisError {
+    fn from(never: std::convert::Infallible) -> Self {
+        match never {}
+    }
 }
 
 impl RedisError {
+    /// Error code that prefixes the message, client libraries switch on it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongType => "WRONGTYPE",
+            Self::NotMaster => "READONLY",
+            Self::NoReplicas => "NOREPLICAS",
+            Self::NoProto => "NOPROTO",
+            Self::WrongPass => "WRONGPASS",
+            _ => "ERR",
+        }
+    }
+
+    /// Errors that keep a command from running at all, counted as rejected calls rather than
+    /// failed ones. Errors of the arguments themselves, like syntax errors, make calls fail.
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            Self::UnknownCommand { .. }
+                | Self::UnknownSubcommand { .. }
+                | Self::WrongArity(_)
+                | Self::NotMaster
+                | Self::NoReplicas
+        )
+    }
+
     pub fn into_response(&self) -> Response {
-        Response::Raw(Bytes::from(format!("-{self}\r\n")))
+        // error replies are single line, so line breaks in the message are replaced
+        let message = self.to_string().replace(['\r', '\n'], " ");
+        Response::Raw(Bytes::from(format!("-{} {message}\r\n", self.code())))
+    }
+}
+
+/// Length after which redis stops adding arguments to the preview of unknown commands.
+const ARGS_PREVIEW_LEN: usize = 128;
+
+fn quoted(args: &[String]) -> String {
+    let mut preview = String::new();
+    let mut len = 0;
+    for arg in args {
+        if len >= ARGS_PREVIEW_LEN {
+            break;
+        }
+        let arg = arg.chars().take(ARGS_PREVIEW_LEN - len).collect::<String>();
+        len += arg.chars().count() + 3;
+        preview.push_str(&format!("'{arg}' "));
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unknown(command: &str, args: &[String]) -> String {
+        RedisError::UnknownCommand {
+            command: command.to_owned(),
+            args: args.to_vec(),
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn unknown_command_preview_is_truncated() {
+        assert_eq!(
+            unknown("FOO", &["a".to_owned(), "b".to_owned()]),
+            "unknown command 'FOO', with args beginning with: 'a' 'b' "
+        );
+
+        let long = "x".repeat(200);
+        assert_eq!(
+            unknown("FOO", &[long.clone(), "b".to_owned()]),
+            format!(
+                "unknown command 'FOO', with args beginning with: '{}' ",
+                &long[..128]
+            )
+        );
+
+        let args = vec!["y".repeat(60); 3];
+        let message = unknown("FOO", &args);
+        let preview = message.split_once(": ").unwrap().1;
+        assert_eq!(
+            preview,
+            format!("'{0}' '{0}' '{1}' ", args[0], &args[0][..2])
+        );
+
+        assert_eq!(
+            unknown(&long, &[]),
+            format!(
+                "unknown command '{}', with args beginning with: ",
+                &long[..128]
+            )
+        );
     }
 }
@@ -0,0 +1,422 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tower::{Layer, Service};
+
+use crate::{
+    config::Config,
+    encoding::resp3::Map,
+    error::RedisError,
+    request::Request,
+    response::Response,
+    routing::{CommandFlag, CommandTable},
+};
+
+/// Samples kept per event.
+const MAX_SAMPLES: usize = 160;
+/// Samples drawn by `LATENCY GRAPH`, one per column.
+const GRAPH_WIDTH: usize = 80;
+const GRAPH_HEIGHT: u64 = 4;
+
+/// Execution of a command that isn't flagged as fast.
+pub const COMMAND: &str = "command";
+pub const FAST_COMMAND: &str = "fast-command";
+/// Serialization of the data set, for full resynchronizations and saves.
+pub const SNAPSHOT: &str = "snapshot";
+
+pub type SharedLatencyMonitor = Arc<LatencyMonitor>;
+
+/// Latency spikes of named events above `latency-monitor-threshold`, and
+/// histograms of the execution time of every command.
+pub struct LatencyMonitor {
+    threshold: Duration,
+    events: Mutex<BTreeMap<&'static str, EventSeries>>,
+    histograms: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+#[derive(Debug, Default)]
+struct EventSeries {
+    samples: VecDeque<Sample>,
+    max: u64,
+}
+
+/// Worst latency in milliseconds observed during a second.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: u64,
+    latency: u64,
+}
+
+/// Reply of `LATENCY LATEST`: event, time and latency of the last spike and
+/// the all time maximum latency.
+#[derive(Debug, Serialize)]
+pub struct LatestSample(&'static str, u64, u64, u64);
+
+/// Calls counted in buckets of powers of two microseconds.
+#[derive(Debug, Clone)]
+struct Histogram {
+    calls: u64,
+    buckets: [u64; 64],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            calls: 0,
+            buckets: [0; 64],
+        }
+    }
+}
+
+/// Reply of `LATENCY HISTOGRAM` per command, counts are cumulative.
+#[derive(Debug, Serialize)]
+pub struct HistogramReply {
+    calls: u64,
+    histogram_usec: Map<u64, u64>,
+}
+
+impl Histogram {
+    fn record(&mut self, elapsed: Duration) {
+        let usec = (elapsed.as_micros() as u64).max(1);
+        let bucket = usec.next_power_of_two().trailing_zeros() as usize;
+        self.calls += 1;
+        self.buckets[bucket.min(63)] += 1;
+    }
+
+    fn reply(&self) -> HistogramReply {
+        let mut total = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| {
+                total += count;
+                (1 << bucket, total)
+            })
+            .collect();
+
+        HistogramReply {
+            calls: self.calls,
+            histogram_usec: Map(buckets),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl LatencyMonitor {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.latency_monitor_threshold,
+            events: Default::default(),
+            histograms: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.threshold.is_zero()
+    }
+
+    /// Records `latency` of `event` if it reaches the threshold.
+    pub fn record(&self, event: &'static str, latency: Duration) {
+        if !self.is_enabled() || latency < self.threshold {
+            return;
+        }
+        let latency = latency.as_millis() as u64;
+        let time = unix_time();
+
+        let mut events = self.events.lock();
+        let series = events.entry(event).or_default();
+        series.max = series.max.max(latency);
+        match series.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                series.samples.push_back(Sample { time, latency });
+                if series.samples.len() > MAX_SAMPLES {
+                    series.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Times `f` as an occurrence of `event`.
+    pub fn measure<T>(&self, event: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(event, start.elapsed());
+        result
+    }
+
+    fn record_command(&self, command: &'static str, elapsed: Duration) {
+        self.histograms
+            .lock()
+            .entry(command)
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn latest(&self) -> Vec<LatestSample> {
+        self.events
+            .lock()
+            .iter()
+            .filter_map(|(event, series)| {
+                let last = series.samples.back()?;
+                Some(LatestSample(event, last.time, last.latency, series.max))
+            })
+            .collect()
+    }
+
+    /// Time and latency of the samples of `event`.
+    pub fn history(&self, event: &str) -> Vec<(u64, u64)> {
+        self.events
+            .lock()
+            .get(event)
+            .map(|it| it.samples.iter().map(|it| (it.time, it.latency)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the given events, or all of them, returning how many were known.
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut known = self.events.lock();
+        if events.is_empty() {
+            let count = known.len();
+            known.clear();
+            return count;
+        }
+        events
+            .iter()
+            .filter(|it| known.remove(it.to_lowercase().as_str()).is_some())
+            .count()
+    }
+
+    /// Histograms of the given commands, or of all of them.
+    pub fn histograms(&self, commands: &[String]) -> Map<&'static str, HistogramReply> {
+        let histograms = self.histograms.lock();
+        let entries = histograms
+            .iter()
+            .filter(|(name, _)| {
+                commands.is_empty() || commands.iter().any(|it| it.eq_ignore_ascii_case(name))
+            })
+            .map(|(name, histogram)| (*name, histogram.reply()))
+            .collect();
+        Map(entries)
+    }
+
+    /// `CONFIG RESETSTAT`
+    pub fn reset_histograms(&self) {
+        self.histograms.lock().clear();
+    }
+
+    /// ASCII art graph of the samples of `event`, oldest on the left.
+    pub fn graph(&self, event: &str) -> Option<String> {
+        let events = self.events.lock();
+        let series = events.get(event)?;
+        let samples = series.samples.iter().rev().take(GRAPH_WIDTH).rev();
+        let samples = samples.copied().collect::<Vec<_>>();
+        let high = samples.iter().map(|it| it.latency).max()?;
+        let low = samples.iter().map(|it| it.latency).min()?;
+
+        let mut output = String::new();
+        writeln!(
+            output,
+            "{event} - high {high} ms, low {low} ms (all time high {} ms)",
+            series.max
+        )
+        .unwrap();
+        writeln!(output, "{}", "-".repeat(GRAPH_WIDTH)).unwrap();
+
+        // heights in half rows, so a bar can end with `_`
+        let levels = samples
+            .iter()
+            .map(|it| match high - low {
+                0 => GRAPH_HEIGHT * 2,
+                range => 1 + (it.latency - low) * (GRAPH_HEIGHT * 2 - 1) / range,
+            })
+            .collect::<Vec<_>>();
+        for row in (0..GRAPH_HEIGHT).rev() {
+            let line = levels
+                .iter()
+                .map(|level| match *level {
+                    it if it >= (row + 2) * 2 => '|',
+                    it if it >= (row + 1) * 2 => '#',
+                    it if it == row * 2 + 1 => '_',
+                    _ => ' ',
+                })
+                .collect::<String>();
+            writeln!(output, "{}", line.trim_end()).unwrap();
+        }
+
+        // ages of the samples, written vertically
+        let now = unix_time();
+        let labels = samples
+            .iter()
+            .map(|it| format_age(now.saturating_sub(it.time)))
+            .collect::<Vec<_>>();
+        let height = labels.iter().map(|it| it.len()).max().unwrap_or_default();
+        writeln!(output).unwrap();
+        for i in 0..height {
+            let line = labels
+                .iter()
+                .map(|it| it.as_bytes().get(i).map_or(' ', |it| *it as char))
+                .collect::<String>();
+            writeln!(output, "{}", line.trim_end()).unwrap();
+        }
+
+        Some(output)
+    }
+
+    /// Human readable analysis of the recorded events.
+    pub fn doctor(&self) -> String {
+        if !self.is_enabled() {
+            return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this \
+                Redis instance. You may start it with --latency-monitor-threshold <milliseconds> \
+                in order to enable it.\n"
+                .to_owned();
+        }
+
+        let events = self.events.lock();
+        if events.is_empty() {
+            return "Dave, no latency spike was observed during the lifetime of this Redis \
+                instance, not in the slightest bit. I honestly think you ought to sleep.\n"
+                .to_owned();
+        }
+
+        let mut output = String::from(
+            "Dave, I have observed latency spikes in this Redis instance. \
+             You don't mind talking about it, do you Dave?\n\n",
+        );
+        for (i, (event, series)) in events.iter().enumerate() {
+            let samples = &series.samples;
+            let count = samples.len() as u64;
+            let average = samples.iter().map(|it| it.latency).sum::<u64>() / count.max(1);
+            let deviation = samples
+                .iter()
+                .map(|it| it.latency.abs_diff(average))
+                .sum::<u64>()
+                / count.max(1);
+            let period = match (samples.front(), samples.back()) {
+                (Some(first), Some(last)) if count > 1 => {
+                    (last.time - first.time) as f64 / (count - 1) as f64
+                }
+                _ => 0.0,
+            };
+            writeln!(
+                output,
+                "{}. {event}: {count} latency spikes (average {average}ms, mean deviation \
+                 {deviation}ms, period {period:.2} sec). Worst all time event {}ms.",
+                i + 1,
+                series.max,
+            )
+            .unwrap();
+        }
+
+        output.push_str("\nI have a few advices for you:\n\n");
+        if events.contains_key(COMMAND) {
+            output.push_str(
+                "- Check your slow log with SLOWLOG GET, slow commands are recorded there. \
+                 Avoid O(N) commands like KEYS on big data sets.\n",
+            );
+        }
+        if events.contains_key(FAST_COMMAND) {
+            output.push_str(
+                "- Even commands that are O(1) are slow, the host may be overloaded or \
+                 the server starved of CPU.\n",
+            );
+        }
+        if events.contains_key(SNAPSHOT) {
+            output.push_str(
+                "- Snapshots copy the whole data set. Avoid frequent full resynchronizations \
+                 of replicas, or use repl-diskless-sync to stream the snapshot instead.\n",
+            );
+        }
+        output
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Records the execution time of the routes it's applied to in the [`LatencyMonitor`].
+#[derive(Clone)]
+pub struct LatencyLayer(pub SharedLatencyMonitor);
+
+impl<S> Layer<S> for LatencyLayer {
+    type Service = RecordLatency<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordLatency {
+            inner,
+            monitor: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordLatency<S> {
+    inner: S,
+    monitor: SharedLatencyMonitor,
+}
+
+impl<S> Service<Request> for RecordLatency<S>
+where
+    S: Service<Request, Response = Response, Error = RedisError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = RedisError;
+    type Future = BoxFuture<'static, Result<Response, RedisError>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let command = req
+            .extensions()
+            .get::<CommandTable>()
+            .and_then(|it| it.get(&req.command))
+            .copied();
+        let monitor = self.monitor.clone();
+        let state = req.state().clone();
+        let blocked = state.blocked_time();
+        let start = Instant::now();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            if let Some(command) = command {
+                // time the client was blocked is not spent executing the command
+                let elapsed = start
+                    .elapsed()
+                    .saturating_sub(state.blocked_time() - blocked);
+                monitor.record_command(command.name, elapsed);
+                match command.has_flag(CommandFlag::Fast) {
+                    true => monitor.record(FAST_COMMAND, elapsed),
+                    false => monitor.record(COMMAND, elapsed),
+                }
+            }
+            result
+        })
+    }
+}
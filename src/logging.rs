@@ -0,0 +1,227 @@
+use std::{
+    fmt::{self, Write},
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+use eyre::WrapErr;
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    filter::EnvFilter,
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        writer::BoxMakeWriter,
+        FmtContext, FormatEvent, FormatFields,
+    },
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    Layer, Registry,
+};
+
+use crate::config::{Config, LogFormat, LogLevel};
+
+pub type SharedLogging = Arc<Logging>;
+
+/// Installed subscriber, its level can be changed at runtime with `CONFIG SET loglevel`.
+pub struct Logging {
+    level: parking_lot::Mutex<LogLevel>,
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl Logging {
+    /// Installs the global subscriber described by the config.
+    pub fn init(config: &Config) -> eyre::Result<SharedLogging> {
+        let (filter, handle) = reload::Layer::new(filter(config.loglevel));
+
+        let writer = match &config.logfile {
+            Some(path) => {
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .wrap_err_with(|| format!("Failed to open log file {}", path.display()))?;
+                BoxMakeWriter::new(Mutex::new(file))
+            }
+            None => BoxMakeWriter::new(std::io::stdout),
+        };
+        let output = tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(config.logfile.is_none())
+            .with_thread_names(true);
+        let output = match config.log_format {
+            LogFormat::Pretty => output.pretty().boxed(),
+            LogFormat::Json => output.event_format(JsonFormat).boxed(),
+        };
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(output)
+            .try_init()?;
+
+        Ok(Arc::new(Self {
+            level: parking_lot::Mutex::new(config.loglevel),
+            filter: handle,
+        }))
+    }
+
+    pub fn level(&self) -> LogLevel {
+        *self.level.lock()
+    }
+
+    pub fn set_level(&self, level: LogLevel) -> eyre::Result<()> {
+        let mut current = self.level.lock();
+        self.filter
+            .reload(filter(level))
+            .wrap_err("Failed to change the log level")?;
+        *current = level;
+        Ok(())
+    }
+}
+
+/// Filter of `level`, refined by the per-module directives of `RUST_LOG`.
+fn filter(level: LogLevel) -> EnvFilter {
+    let level = match level {
+        LogLevel::Debug => LevelFilter::TRACE,
+        LogLevel::Verbose => LevelFilter::DEBUG,
+        LogLevel::Notice => LevelFilter::INFO,
+        LogLevel::Warning => LevelFilter::WARN,
+        LogLevel::Nothing => LevelFilter::OFF,
+    };
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_default();
+    EnvFilter::builder().parse_lossy(format!("{level},{directives}"))
+}
+
+/// Events as JSON objects, one per line.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        write!(writer, "{{\"timestamp\":")?;
+        write_json_string(&mut writer, &timestamp)?;
+        write!(writer, ",\"level\":\"{}\",\"target\":", metadata.level())?;
+        write_json_string(&mut writer, metadata.target())?;
+        if let Some(name) = std::thread::current().name() {
+            write!(writer, ",\"thread\":")?;
+            write_json_string(&mut writer, name)?;
+        }
+
+        if let Some(scope) = ctx.event_scope() {
+            write!(writer, ",\"spans\":[")?;
+            for (i, span) in scope.from_root().enumerate() {
+                if i > 0 {
+                    write!(writer, ",")?;
+                }
+                write_json_string(&mut writer, span.name())?;
+            }
+            write!(writer, "]")?;
+        }
+
+        let mut fields = JsonFields(String::new());
+        event.record(&mut fields);
+        writeln!(writer, ",\"fields\":{{{}}}}}", fields.0)
+    }
+}
+
+/// Fields of an event as the members of a JSON object.
+struct JsonFields(String);
+
+impl JsonFields {
+    fn key(&mut self, field: &Field) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        write_json_string(&mut self.0, field.name()).unwrap();
+        self.0.push(':');
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.key(field);
+        write!(self.0, "{value}").unwrap();
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.key(field);
+        write!(self.0, "{value}").unwrap();
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.key(field);
+        write!(self.0, "{value}").unwrap();
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.key(field);
+        write_json_string(&mut self.0, value).unwrap();
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.key(field);
+        write_json_string(&mut self.0, &format!("{value:?}")).unwrap();
+    }
+}
+
+fn write_json_string(output: &mut impl Write, value: &str) -> fmt::Result {
+    output.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => output.write_str("\\\"")?,
+            '\\' => output.write_str("\\\\")?,
+            '\n' => output.write_str("\\n")?,
+            '\r' => output.write_str("\\r")?,
+            '\t' => output.write_str("\\t")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32)?,
+            c => output.write_char(c)?,
+        }
+    }
+    output.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(value: &str) -> String {
+        let mut output = String::new();
+        write_json_string(&mut output, value).unwrap();
+        output
+    }
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!(json(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(json("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json("\u{0}\u{1b}\u{7f}"), r#""\u0000\u001b\u007f""#);
+    }
+
+    #[test]
+    fn other_characters_are_kept() {
+        assert_eq!(json("é ✓ 'x' /"), "\"é ✓ 'x' /\"");
+        assert_eq!(json(""), "\"\"");
+    }
+}
@@ -1,40 +1,75 @@
+mod allocator;
+mod clients;
 mod commands;
 mod config;
 mod encoding;
 mod engine;
 mod error;
+mod latency;
+mod logging;
+mod metrics;
+mod monitor;
 mod network;
 mod replication;
 mod request;
 mod response;
 mod routing;
+mod shutdown;
+mod slowlog;
 mod state;
+mod stats;
 mod storage;
 mod util;
 mod value;
 
-use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    fs::Permissions,
+    net::{IpAddr, SocketAddr},
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use bytes::{Buf, Bytes, BytesMut};
 use clap::Parser;
-use encoding::resp2;
+use encoding::{inline, resp2};
 use eyre::{bail, eyre, WrapErr};
+use futures_util::{future::try_join_all, FutureExt};
 use tokio::{
-    io::AsyncReadExt,
-    net::{TcpListener, TcpStream},
-    sync::mpsc,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    select,
+    sync::{broadcast, mpsc},
+    time::sleep,
 };
+use tokio_rustls::TlsStream;
+use tokio_util::sync::CancellationToken;
 use tower::ServiceExt;
 
 use crate::{
-    config::Config,
+    commands::table,
+    config::{
+        ClientClass, Config, LogFormat, LogLevel, OutputBufferLimit, OutputBufferLimits,
+        TlsAuthClients,
+    },
     error::RedisError,
-    network::{NetworkExt, NodeId, RedisNetwork},
+    latency::{LatencyLayer, LatencyMonitor},
+    logging::{Logging, SharedLogging},
+    metrics::{serve_metrics, Metrics},
+    monitor::{MonitorFeed, MonitorLayer, SharedMonitors},
+    network::{
+        Listener, NetworkExt, NodeId, PeerAddr, RedisNetwork, TlsAddr, TlsContext, TlsListener,
+        Transport,
+    },
     replication::{master::ReplicaConnectionQueue, ReplicationState, Topology},
     request::Extension,
-    response::IntoResponse,
+    response::{IntoResponse, OutputBuffer},
     routing::{Request, Response, Router},
+    shutdown::{Connections, Shutdown},
+    slowlog::{SlowLog, SlowLogLayer},
     state::ConnectionState,
+    stats::{SharedStats, StatsLayer},
 };
 
 #[derive(Parser, Debug)]
@@ -43,6 +78,52 @@ struct Args {
     #[arg(long, default_value = "6379")]
     port: u16,
 
+    /// Interfaces to listen on, `*` and `::*` bind every IPv4 and IPv6 interface.
+    #[arg(long, num_args = 1.., default_value = "0.0.0.0", value_parser = config::parse_bind_addr)]
+    pub bind: Vec<IpAddr>,
+
+    #[arg(long)]
+    pub unixsocket: Option<PathBuf>,
+
+    #[arg(long, value_parser = config::parse_permissions)]
+    pub unixsocketperm: Option<u32>,
+
+    #[arg(long = "tls-port", default_value = "0")]
+    pub tls_port: u16,
+
+    #[arg(long = "tls-cert-file")]
+    pub tls_cert_file: Option<PathBuf>,
+
+    #[arg(long = "tls-key-file")]
+    pub tls_key_file: Option<PathBuf>,
+
+    #[arg(long = "tls-ca-cert-file")]
+    pub tls_ca_cert_file: Option<PathBuf>,
+
+    #[arg(
+        long = "tls-auth-clients",
+        default_value = "yes",
+        value_parser = config::parse_tls_auth_clients
+    )]
+    pub tls_auth_clients: TlsAuthClients,
+
+    #[arg(
+        long = "tls-replication",
+        default_value = "no",
+        value_parser = config::parse_yes_no,
+        action = clap::ArgAction::Set
+    )]
+    pub tls_replication: bool,
+
+    #[arg(long, default_value = "0")]
+    pub timeout: u64,
+
+    #[arg(long = "tcp-keepalive", default_value = "300")]
+    pub tcp_keepalive: u64,
+
+    #[arg(long, default_value = "10000")]
+    pub maxclients: usize,
+
     #[arg(long, number_of_values = 2)]
     replicaof: Option<Vec<String>>,
 
@@ -51,19 +132,152 @@ struct Args {
 
     #[arg(long)]
     pub dbfilename: Option<String>,
+
+    #[arg(long = "repl-timeout", default_value = "60")]
+    pub repl_timeout: u64,
+
+    #[arg(long = "min-replicas-to-write", default_value = "0")]
+    pub min_replicas_to_write: usize,
+
+    #[arg(long = "min-replicas-max-lag", default_value = "10")]
+    pub min_replicas_max_lag: u64,
+
+    #[arg(
+        long = "repl-diskless-sync",
+        default_value = "no",
+        value_parser = config::parse_yes_no,
+        action = clap::ArgAction::Set
+    )]
+    pub repl_diskless_sync: bool,
+
+    #[arg(
+        long = "client-query-buffer-limit",
+        default_value = "1gb",
+        value_parser = config::parse_memory
+    )]
+    pub client_query_buffer_limit: usize,
+
+    #[arg(
+        long = "proto-max-bulk-len",
+        default_value = "512mb",
+        value_parser = config::parse_memory
+    )]
+    pub proto_max_bulk_len: usize,
+
+    #[arg(
+        long = "client-output-buffer-limit",
+        value_parser = config::parse_output_buffer_limit
+    )]
+    pub client_output_buffer_limit: Vec<(ClientClass, OutputBufferLimit)>,
+
+    #[arg(long = "shutdown-timeout", default_value = "10")]
+    pub shutdown_timeout: u64,
+
+    #[arg(
+        long = "slowlog-log-slower-than",
+        default_value = "10000",
+        allow_hyphen_values = true
+    )]
+    pub slowlog_log_slower_than: i64,
+
+    #[arg(long = "slowlog-max-len", default_value = "128")]
+    pub slowlog_max_len: usize,
+
+    #[arg(long = "latency-monitor-threshold", default_value = "0")]
+    pub latency_monitor_threshold: u64,
+
+    /// Port serving Prometheus metrics over HTTP, `0` disables it.
+    #[arg(long = "metrics-port", default_value = "0")]
+    pub metrics_port: u16,
+
+    /// One of `debug`, `verbose`, `notice`, `warning` and `nothing`, refined per module with
+    /// `RUST_LOG`.
+    #[arg(long, default_value = "notice", value_parser = config::parse_loglevel)]
+    pub loglevel: LogLevel,
+
+    /// Logs are appended to this file instead of the standard output.
+    #[arg(long)]
+    pub logfile: Option<PathBuf>,
+
+    #[arg(long = "log-format", default_value = "pretty", value_parser = config::parse_log_format)]
+    pub log_format: LogFormat,
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     stable_eyre::install()?;
-    logging();
     let Args {
         port,
+        bind,
+        unixsocket,
+        unixsocketperm,
+        tls_port,
+        tls_cert_file,
+        tls_key_file,
+        tls_ca_cert_file,
+        tls_auth_clients,
+        tls_replication,
+        timeout,
+        tcp_keepalive,
+        maxclients,
         replicaof,
         dir,
         dbfilename,
+        repl_timeout,
+        min_replicas_to_write,
+        min_replicas_max_lag,
+        repl_diskless_sync,
+        client_query_buffer_limit,
+        proto_max_bulk_len,
+        client_output_buffer_limit,
+        shutdown_timeout,
+        slowlog_log_slower_than,
+        slowlog_max_len,
+        latency_monitor_threshold,
+        metrics_port,
+        loglevel,
+        logfile,
+        log_format,
     } = Args::parse();
-    let config = Arc::new(Config { dir, dbfilename });
+    let mut output_buffer_limits = OutputBufferLimits::default();
+    for (class, limit) in client_output_buffer_limit {
+        output_buffer_limits.set(class, limit);
+    }
+    let config = Arc::new(Config {
+        port,
+        bind,
+        unixsocket,
+        unixsocketperm,
+        tls_port,
+        tls_cert_file,
+        tls_key_file,
+        tls_ca_cert_file,
+        tls_auth_clients,
+        tls_replication,
+        timeout: Duration::from_secs(timeout),
+        tcp_keepalive: Duration::from_secs(tcp_keepalive),
+        maxclients,
+        dir,
+        dbfilename,
+        repl_timeout: Duration::from_secs(repl_timeout),
+        min_replicas_to_write,
+        min_replicas_max_lag: Duration::from_secs(min_replicas_max_lag),
+        repl_diskless_sync,
+        client_query_buffer_limit,
+        proto_max_bulk_len,
+        client_output_buffer_limit: output_buffer_limits,
+        shutdown_timeout: Duration::from_secs(shutdown_timeout),
+        slowlog_log_slower_than: u64::try_from(slowlog_log_slower_than)
+            .ok()
+            .map(Duration::from_micros),
+        slowlog_max_len,
+        latency_monitor_threshold: Duration::from_millis(latency_monitor_threshold),
+        metrics_port,
+        loglevel,
+        logfile,
+        log_format,
+    });
+    let logging = Logging::init(&config)?;
 
     let replicaof = match replicaof.as_deref() {
         Some([host, port]) => {
@@ -82,108 +296,399 @@ async fn main() -> eyre::Result<()> {
         _ => panic!("Wrong arguments"),
     };
 
-    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
-    tracing::info!(addr = ?listener.local_addr().unwrap(), replica_of = ?replicaof, ?config, "Starting to listen on");
+    let tls = TlsContext::load(&config)?;
+    let listeners = Listeners::bind(&config, tls.as_ref()).await?;
+    tracing::info!(replica_of = ?replicaof, ?config, "Starting to listen on");
 
-    match replicaof {
-        None => {
-            master(listener, config).await?;
-        }
+    let result = match replicaof {
+        None => master(listeners, config.clone(), logging).await,
         Some(addr) => {
-            replica(listener, port, addr, config).await?;
+            let tls = tls.filter(|_| config.tls_replication);
+            replica(listeners, addr, config.clone(), tls, logging).await
         }
+    };
+
+    if let Some(path) = &config.unixsocket {
+        let _ = std::fs::remove_file(path);
     }
 
-    Ok(())
+    result
+}
+
+/// Sockets accepting clients, all of them are served by the same router.
+struct Listeners {
+    tcp: Vec<TcpListener>,
+    tls: Vec<TlsListener>,
+    unix: Option<UnixListener>,
+    /// HTTP listeners of the Prometheus metrics.
+    metrics: Vec<TcpListener>,
 }
 
-async fn master(listener: TcpListener, config: Arc<Config>) -> eyre::Result<()> {
-    let (storage, replication_queue) = engine::create_engine(&config)?;
+impl Listeners {
+    async fn bind(config: &Config, tls_context: Option<&TlsContext>) -> eyre::Result<Self> {
+        let mut tcp = Vec::with_capacity(config.bind.len());
+        for ip in &config.bind {
+            let listener = TcpStream::bind(&SocketAddr::new(*ip, config.port))
+                .await
+                .wrap_err_with(|| format!("Failed to listen on {ip}:{}", config.port))?;
+            tracing::info!(addr = ?listener.local_addr()?, "Listening on TCP");
+            tcp.push(listener);
+        }
+
+        let mut tls = Vec::new();
+        if let Some(context) = tls_context.filter(|_| config.tls_port != 0) {
+            for ip in &config.bind {
+                let address = TlsAddr {
+                    addr: SocketAddr::new(*ip, config.tls_port),
+                    context: context.clone(),
+                };
+                let listener = TlsStream::<TcpStream>::bind(&address)
+                    .await
+                    .wrap_err_with(|| format!("Failed to listen on {ip}:{}", config.tls_port))?;
+                tracing::info!(addr = ?listener.local_addr()?, "Listening on TLS");
+                tls.push(listener);
+            }
+        }
+
+        let unix = match &config.unixsocket {
+            Some(path) => {
+                let listener = UnixStream::bind(path)
+                    .await
+                    .wrap_err_with(|| format!("Failed to listen on {}", path.display()))?;
+                if let Some(mode) = config.unixsocketperm {
+                    std::fs::set_permissions(path, Permissions::from_mode(mode))
+                        .wrap_err("Failed to set unix socket permissions")?;
+                }
+                tracing::info!(path = %path.display(), "Listening on unix socket");
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let mut metrics = Vec::new();
+        if config.metrics_port != 0 {
+            for ip in &config.bind {
+                let listener = TcpListener::bind(SocketAddr::new(*ip, config.metrics_port))
+                    .await
+                    .wrap_err_with(|| {
+                        format!("Failed to listen on {ip}:{}", config.metrics_port)
+                    })?;
+                tracing::info!(addr = ?listener.local_addr()?, "Serving metrics over HTTP");
+                metrics.push(listener);
+            }
+        }
+
+        Ok(Self {
+            tcp,
+            tls,
+            unix,
+            metrics,
+        })
+    }
+}
+
+async fn master(
+    listeners: Listeners,
+    config: Arc<Config>,
+    logging: SharedLogging,
+) -> eyre::Result<()> {
+    let latency = Arc::new(LatencyMonitor::new(&config));
+    let (storage, replication_queue) = engine::create_engine(&config, latency.clone())?;
     let state = ReplicationState::master();
     let topology = Topology::master();
     let (new_replicas, wait_queue) = replication::master::initiate(
+        config.clone(),
         storage.clone(),
         topology.clone(),
         state.clone(),
         replication_queue,
     )?;
+    let (shutdown_queue, shutdown_requests) = Shutdown::queue();
+    let connections = Connections::default();
+    let stats = SharedStats::default();
+    let slowlog = Arc::new(SlowLog::new(&config));
+    let monitors = SharedMonitors::default();
 
     let router = Router::new()
-        .route("ping", commands::ping)
-        .route("echo", commands::echo)
-        .route("get", commands::get)
-        .route("set", commands::set)
-        .route("info", commands::info)
-        .route("replconf", commands::repl::config)
-        .route("psync", commands::repl::psync)
-        .route("wait", commands::repl::wait)
-        .route("config", commands::config)
-        .route("keys", commands::keys)
-        .route("type", commands::key_type)
-        .route("xadd", commands::stream::xadd)
-        .route("xrange", commands::stream::xrange)
-        .route("xread", commands::stream::xread)
-        .layer(Extension(config))
-        .layer(Extension(wait_queue))
-        .layer(Extension(state))
-        .layer(Extension(topology))
-        .layer(Extension(storage));
-
-    serve_connections(listener, router, Some(new_replicas)).await
+        .route(table::PING, commands::ping)
+        .route(table::ECHO, commands::echo)
+        .route(table::HELLO, commands::connection::hello)
+        .route(table::GET, commands::get)
+        .route(table::SET, commands::set)
+        .route(table::INFO, commands::info::info)
+        .route(table::REPLCONF, commands::repl::config)
+        .route(table::PSYNC, commands::repl::psync)
+        .route(table::WAIT, commands::repl::wait)
+        .route(table::CONFIG, config_router())
+        .route(table::KEYS, commands::keys)
+        .route(table::TYPE, commands::key_type)
+        .route(table::XADD, commands::stream::xadd)
+        .route(table::XRANGE, commands::stream::xrange)
+        .route(table::XREAD, commands::stream::xread)
+        .route(table::SHUTDOWN, commands::server::shutdown)
+        .route(table::CLIENT, client_router())
+        .route(table::COMMAND, commands::server::command)
+        .route(table::SLOWLOG, slowlog_router())
+        .route(table::LATENCY, latency_router())
+        .route(table::MONITOR, commands::server::monitor)
+        .layer(StatsLayer(stats.clone()))
+        .layer(SlowLogLayer(slowlog.clone()))
+        .layer(LatencyLayer(latency.clone()))
+        .layer(MonitorLayer(monitors.clone()))
+        .layer(Extension(config.clone()))
+        .layer(Extension(wait_queue.clone()))
+        .layer(Extension(state.clone()))
+        .layer(Extension(topology.clone()))
+        .layer(Extension(storage.clone()))
+        .layer(Extension(shutdown_queue))
+        .layer(Extension(connections.clients.clone()))
+        .layer(Extension(stats.clone()))
+        .layer(Extension(slowlog))
+        .layer(Extension(latency))
+        .layer(Extension(monitors))
+        .layer(Extension(logging));
+
+    let metrics = Arc::new(Metrics {
+        config: config.clone(),
+        stats,
+        clients: connections.clients.clone(),
+        storage: storage.clone(),
+        state: state.clone(),
+        topology: topology.clone(),
+    });
+    let shutdown = Shutdown {
+        config: config.clone(),
+        engine: storage,
+        state,
+        topology,
+        waits: Some(wait_queue),
+        connections: connections.clone(),
+    };
+    shutdown
+        .run(
+            serve_connections(
+                listeners,
+                config,
+                router,
+                new_replicas,
+                connections,
+                metrics,
+            ),
+            shutdown_requests,
+        )
+        .await
 }
 
 async fn replica(
-    listener: TcpListener,
-    port: u16,
+    listeners: Listeners,
     master: SocketAddr,
     config: Arc<Config>,
+    tls: Option<TlsContext>,
+    logging: SharedLogging,
 ) -> eyre::Result<()> {
     let master = NodeId::master(master);
     let topology = Topology::replica(master);
-    let mut network = RedisNetwork::new(Some(master)).await?;
-    let state = handshake(master, port, &mut network).await?;
-    let (storage, acks) = engine::create_engine(&config)?;
+    let mut network = RedisNetwork::new(Some(master), tls).await?;
+    let state = handshake(master, config.port, &mut network).await?;
+    let latency = Arc::new(LatencyMonitor::new(&config));
+    let (storage, acks) = engine::create_engine(&config, latency.clone())?;
+    let (new_replicas, clients) = mpsc::channel(4);
 
     tokio::spawn(replication::replica::start(
+        config.clone(),
         network,
         master,
         storage.clone(),
         state.clone(),
+        topology.clone(),
         acks,
+        clients,
     ));
+    let (shutdown_queue, shutdown_requests) = Shutdown::queue();
+    let connections = Connections::default();
+    let stats = SharedStats::default();
+    let slowlog = Arc::new(SlowLog::new(&config));
+    let monitors = SharedMonitors::default();
 
     let router = Router::new()
-        .route("ping", commands::ping)
-        .route("echo", commands::echo)
-        .route("get", commands::get)
-        .route("info", commands::info)
-        .route("replconf", commands::repl::config)
-        .route("config", commands::config)
-        .route("keys", commands::keys)
-        .route("type", commands::key_type)
-        .route("xrange", commands::stream::xrange)
-        .route("xread", commands::stream::xread)
-        .layer(Extension(config))
-        .layer(Extension(state))
-        .layer(Extension(topology))
-        .layer(Extension(storage));
-
-    serve_connections(listener, router, None).await
+        .route(table::PING, commands::ping)
+        .route(table::ECHO, commands::echo)
+        .route(table::HELLO, commands::connection::hello)
+        .route(table::GET, commands::get)
+        .route(table::INFO, commands::info::info)
+        .route(table::REPLCONF, commands::repl::config)
+        .route(table::PSYNC, commands::repl::psync)
+        .route(table::CONFIG, config_router())
+        .route(table::KEYS, commands::keys)
+        .route(table::TYPE, commands::key_type)
+        .route(table::XRANGE, commands::stream::xrange)
+        .route(table::XREAD, commands::stream::xread)
+        .route(table::SHUTDOWN, commands::server::shutdown)
+        .route(table::CLIENT, client_router())
+        .route(table::COMMAND, commands::server::command)
+        .route(table::SLOWLOG, slowlog_router())
+        .route(table::LATENCY, latency_router())
+        .route(table::MONITOR, commands::server::monitor)
+        .layer(StatsLayer(stats.clone()))
+        .layer(SlowLogLayer(slowlog.clone()))
+        .layer(LatencyLayer(latency.clone()))
+        .layer(MonitorLayer(monitors.clone()))
+        .layer(Extension(config.clone()))
+        .layer(Extension(state.clone()))
+        .layer(Extension(topology.clone()))
+        .layer(Extension(storage.clone()))
+        .layer(Extension(shutdown_queue))
+        .layer(Extension(connections.clients.clone()))
+        .layer(Extension(stats.clone()))
+        .layer(Extension(slowlog))
+        .layer(Extension(latency))
+        .layer(Extension(monitors))
+        .layer(Extension(logging));
+
+    let metrics = Arc::new(Metrics {
+        config: config.clone(),
+        stats,
+        clients: connections.clients.clone(),
+        storage: storage.clone(),
+        state: state.clone(),
+        topology: topology.clone(),
+    });
+    let shutdown = Shutdown {
+        config: config.clone(),
+        engine: storage,
+        state,
+        topology,
+        waits: None,
+        connections: connections.clone(),
+    };
+    shutdown
+        .run(
+            serve_connections(
+                listeners,
+                config,
+                router,
+                new_replicas,
+                connections,
+                metrics,
+            ),
+            shutdown_requests,
+        )
+        .await
+}
+
+fn config_router() -> Router {
+    Router::new()
+        .route(table::CONFIG_GET, commands::config_get)
+        .route(table::CONFIG_SET, commands::config_set)
+        .route(table::CONFIG_RESETSTAT, commands::config_resetstat)
+}
+
+fn client_router() -> Router {
+    use commands::client;
+
+    Router::new()
+        .route(table::CLIENT_ID, client::id)
+        .route(table::CLIENT_INFO, client::info)
+        .route(table::CLIENT_LIST, client::list)
+        .route(table::CLIENT_GETNAME, client::getname)
+        .route(table::CLIENT_SETNAME, client::setname)
+        .route(table::CLIENT_KILL, client::kill)
+        .route(table::CLIENT_PAUSE, client::pause)
+        .route(table::CLIENT_UNPAUSE, client::unpause)
+        .route(table::CLIENT_NO_EVICT, client::no_evict)
+}
+
+fn slowlog_router() -> Router {
+    use commands::server;
+
+    Router::new()
+        .route(table::SLOWLOG_GET, server::slowlog_get)
+        .route(table::SLOWLOG_LEN, server::slowlog_len)
+        .route(table::SLOWLOG_RESET, server::slowlog_reset)
+}
+
+fn latency_router() -> Router {
+    use commands::latency;
+
+    Router::new()
+        .route(table::LATENCY_LATEST, latency::latest)
+        .route(table::LATENCY_HISTORY, latency::history)
+        .route(table::LATENCY_RESET, latency::reset)
+        .route(table::LATENCY_GRAPH, latency::graph)
+        .route(table::LATENCY_DOCTOR, latency::doctor)
+        .route(table::LATENCY_HISTOGRAM, latency::histogram)
 }
 
 async fn serve_connections(
-    listener: TcpListener,
+    listeners: Listeners,
+    config: Arc<Config>,
+    router: Router,
+    new_replicas: ReplicaConnectionQueue,
+    connections: Connections,
+    metrics: Arc<Metrics>,
+) -> eyre::Result<()> {
+    let mut serving = Vec::new();
+    for listener in listeners.metrics {
+        serving.push(serve_metrics(listener, metrics.clone()).boxed());
+    }
+    for listener in listeners.tcp {
+        serving.push(
+            accept_connections(
+                listener,
+                config.clone(),
+                router.clone(),
+                new_replicas.clone(),
+                connections.clone(),
+            )
+            .boxed(),
+        );
+    }
+    for listener in listeners.tls {
+        serving.push(
+            accept_connections(
+                listener,
+                config.clone(),
+                router.clone(),
+                new_replicas.clone(),
+                connections.clone(),
+            )
+            .boxed(),
+        );
+    }
+    if let Some(listener) = listeners.unix {
+        serving
+            .push(accept_connections(listener, config, router, new_replicas, connections).boxed());
+    }
+
+    try_join_all(serving).await?;
+    Ok(())
+}
+
+async fn accept_connections<L: Listener>(
+    mut listener: L,
+    config: Arc<Config>,
     router: Router,
-    new_replicas: Option<ReplicaConnectionQueue>,
+    new_replicas: ReplicaConnectionQueue,
+    connections: Connections,
 ) -> eyre::Result<()> {
     loop {
         let (incoming, addr) = listener.accept().await?;
+        let addr: PeerAddr = addr.into();
+        if !config.tcp_keepalive.is_zero() {
+            if let Err(error) = incoming.set_keepalive(config.tcp_keepalive) {
+                tracing::warn!(%addr, %error, "Failed to enable TCP keepalive");
+            }
+        }
+        let config = config.clone();
         let router = router.clone();
         let new_replicas = new_replicas.clone();
-        tokio::spawn(async move {
-            serve(addr, incoming, router, new_replicas.clone())
-                .await
-                .unwrap();
+        let shared = connections.clone();
+        connections.tracker.spawn(async move {
+            if let Err(error) =
+                serve(addr.clone(), incoming, config, router, new_replicas, shared).await
+            {
+                tracing::warn!(%addr, ?error, "Connection failed");
+            }
         });
     }
 }
@@ -235,74 +740,186 @@ async fn handshake(
     Ok(replication_state)
 }
 
-async fn serve(
-    addr: SocketAddr,
-    mut connection: TcpStream,
+/// Initial size of the query buffer, so a single read picks up many pipelined requests.
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+async fn serve<T: Transport>(
+    addr: PeerAddr,
+    mut connection: T,
+    config: Arc<Config>,
     router: Router,
-    new_replicas: Option<ReplicaConnectionQueue>,
+    new_replicas: ReplicaConnectionQueue,
+    connections: Connections,
 ) -> eyre::Result<()> {
     tracing::info!(addr = %addr, "Accepted new connection");
-    let mut buf = BytesMut::new();
-    let (mut read, mut write) = connection.split();
-    let state = ConnectionState::new(addr);
+    let mut buf = BytesMut::with_capacity(READ_BUFFER_SIZE);
+    let state = ConnectionState::new(addr.clone());
+    let _registration = connections.clients.register(state.clone());
+    let killed = state.killed();
+    let mut output = OutputBuffer::new(config.client_output_buffer_limit.get(state.class()));
+
+    if connections.clients.len() > config.maxclients {
+        tracing::warn!(%addr, "Rejecting client, max number of clients reached");
+        connections.clients.record_rejected();
+        output.push(RedisError::MaxClients.into_response(), state.protocol())?;
+        output.flush(&mut connection).await?;
+        return Ok(());
+    }
 
     loop {
-        let res = read
-            .read_buf(&mut buf)
-            .await
-            .wrap_err("Failed to read input")?;
+        // requests in flight are finished, but new ones aren't accepted after shutdown
+        let res = select! {
+            res = connection.read_buf(&mut buf) => res.wrap_err("Failed to read input")?,
+            _ = connections.closed.cancelled() => break,
+            _ = killed.cancelled() => break,
+            // blocked clients aren't reading, so only idle ones time out
+            _ = sleep(config.timeout), if !config.timeout.is_zero() && state.class() != ClientClass::Replica => {
+                tracing::info!(%addr, "Closing idle client");
+                break;
+            }
+        };
 
+        connections.clients.record_input(res);
         if res == 0 && buf.is_empty() {
             break;
         }
 
-        tracing::debug!(count = res, buffer = ?buf, "read bytes");
-
-        let Ok((request, count)) = resp2::from_bytes::<Vec<String>>(buf.as_ref())
-            .wrap_err("Failed to deserialize request")
-        else {
-            continue;
-        };
-        buf.advance(count);
-
-        tracing::debug!(?request, "received a request");
+        // the buffer itself isn't logged, it may hold credentials
+        tracing::trace!(count = res, "read bytes");
 
-        if request.len() == 0 {
-            continue;
-        }
-        let request = Request::from_command_line(request, state.clone())?;
-        let response = router.clone().oneshot(request).await.into_response();
+        // execute every complete request that is already buffered, replies are written together
+        while !killed.is_cancelled() {
+            let parsed = if buf.first() == Some(&b'*') {
+                resp2::from_bytes_with_limit::<Vec<String>>(buf.as_ref(), config.proto_max_bulk_len)
+            } else {
+                inline::from_bytes(buf.as_ref())
+            };
+            let (request, count) = match parsed {
+                Ok(parsed) => parsed,
+                Err(encoding::Error::Incomplete) => break,
+                Err(error) => {
+                    tracing::warn!(%addr, %error, "Protocol error from client");
+                    output.push(
+                        RedisError::Protocol(error.to_string()).into_response(),
+                        state.protocol(),
+                    )?;
+                    output.flush(&mut connection).await?;
+                    return Ok(());
+                }
+            };
+            buf.advance(count);
 
-        if let &Response::Upgrade { offset } = &response {
-            return match new_replicas
-                .unwrap()
-                .send((connection, state.node_id().unwrap(), offset))
-                .await
-            {
-                Ok(_) => Ok(()),
-                Err(mpsc::error::SendError((mut connection, _, _))) => {
-                    RedisError::Unhandled(eyre!("Can't add new replica"))
+            if request.is_empty() {
+                continue;
+            }
+            let request = Request::from_command_line(request, state.clone())?;
+            tracing::trace!(argv = ?request.redacted_argv(), "received a request");
+            // replicas are never paused, as they only send acknowledgements
+            if state.class() != ClientClass::Replica {
+                connections.clients.paused(false).await;
+            }
+            state.set_last_command(&request.command);
+            let response = router.clone().oneshot(request).await.into_response();
+            // `REPLCONF` turns the connection into a replica, which has its own limits
+            output.set_limit(config.client_output_buffer_limit.get(state.class()));
+
+            if let &Response::Upgrade { offset } = &response {
+                output.flush(&mut connection).await?;
+                let Some(node) = state.node_id() else {
+                    RedisError::PsyncWithoutReplconf
                         .into_response()
-                        .write(&mut connection)
+                        .write(&mut connection, state.protocol())
                         .await?;
+                    return Ok(());
+                };
+                let connection = match connection.into_link() {
+                    Ok(connection) => connection,
+                    Err(mut connection) => {
+                        RedisError::Unhandled(eyre!("replicas must connect over TCP or TLS"))
+                            .into_response()
+                            .write(&mut connection, state.protocol())
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                return match new_replicas.send((connection, node, offset)).await {
+                    Ok(_) => Ok(()),
+                    Err(mpsc::error::SendError((mut connection, _, _))) => {
+                        RedisError::Unhandled(eyre!("Can't add new replica"))
+                            .into_response()
+                            .write(&mut connection, state.protocol())
+                            .await?;
+
+                        Err(eyre!("Can't add new replica"))
+                    }
+                };
+            }
 
-                    Err(eyre!("Can't add new replica"))
-                }
-            };
+            if let Response::Monitor(feed) = response {
+                output.push("OK".into_response(), state.protocol())?;
+                output.flush(&mut connection).await?;
+                return monitor(&addr, connection, feed, &connections, &killed).await;
+            }
+
+            if let Err(error) = output.push(response, state.protocol()) {
+                tracing::warn!(%addr, %error, "Closing client that reached output buffer limit");
+                return Ok(());
+            }
         }
 
-        response.write(&mut write).await?;
+        state.set_buffers(buf.len(), output.len());
+        connections.clients.record_output(output.len());
+        if let Err(error) = output.flush(&mut connection).await {
+            tracing::warn!(%addr, %error, "Failed to write replies, closing client");
+            return Ok(());
+        }
+
+        if res == 0 {
+            break;
+        }
+        if buf.len() > config.client_query_buffer_limit {
+            tracing::warn!(%addr, size = buf.len(), "Closing client that reached max query buffer length");
+            break;
+        }
     }
 
     Ok(())
 }
 
-fn logging() {
-    tracing_subscriber::fmt()
-        .pretty()
-        .with_thread_names(true)
-        // enable everything
-        .with_max_level(tracing::Level::TRACE)
-        // sets this to be the default, global collector for this application.
-        .init();
+/// Writes the feed of `MONITOR` to the connection until it's closed, the input is discarded.
+async fn monitor<T: Transport>(
+    addr: &PeerAddr,
+    mut connection: T,
+    mut feed: MonitorFeed,
+    connections: &Connections,
+    killed: &CancellationToken,
+) -> eyre::Result<()> {
+    let mut buf = BytesMut::with_capacity(READ_BUFFER_SIZE);
+    loop {
+        select! {
+            line = feed.recv() => match line {
+                Ok(line) => {
+                    connections.clients.record_output(line.len());
+                    if let Err(error) = connection.write_all(&line).await {
+                        tracing::warn!(%addr, %error, "Failed to write to monitor, closing client");
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    tracing::warn!(%addr, count, "Closing monitor that fell behind");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            res = connection.read_buf(&mut buf) => {
+                if res.wrap_err("Failed to read input")? == 0 {
+                    break;
+                }
+                buf.clear();
+            }
+            _ = connections.closed.cancelled() => break,
+            _ = killed.cancelled() => break,
+        }
+    }
+    Ok(())
 }
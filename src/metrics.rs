@@ -0,0 +1,447 @@
+use std::{
+    fmt::{Display, Write},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    allocator,
+    clients::SharedClients,
+    commands::info::REDIS_VERSION,
+    config::Config,
+    engine::{Keyspace, Lookups, SharedEngine},
+    replication::{ReplicationState, SharedTopology},
+    stats::SharedStats,
+};
+
+/// Largest request head accepted, metrics requests have no body.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters behind `INFO`, exposed in the Prometheus text format on `metrics-port`.
+pub struct Metrics {
+    pub config: Arc<Config>,
+    pub stats: SharedStats,
+    pub clients: SharedClients,
+    pub storage: SharedEngine,
+    pub state: ReplicationState,
+    pub topology: SharedTopology,
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let mut output = Exposition::default();
+        self.write_server(&mut output);
+        self.write_clients(&mut output);
+        self.write_commands(&mut output, &self.storage.lookups());
+        write_keyspace(&mut output, &self.storage.keyspace());
+        self.write_memory(&mut output);
+        self.write_replication(&mut output);
+        self.write_persistence(&mut output);
+        output.0
+    }
+
+    fn write_server(&self, output: &mut Exposition) {
+        output.gauge("redis_up", "Whether the server is up.", 1);
+        output.family(
+            "redis_instance_info",
+            "gauge",
+            "Information about the server.",
+        );
+        output.sample(
+            "redis_instance_info",
+            &[
+                ("redis_version", REDIS_VERSION),
+                ("redis_mode", "standalone"),
+                ("role", &self.state.role().to_string()),
+                ("run_id", &self.stats.run_id),
+                ("tcp_port", &self.config.port.to_string()),
+            ],
+            1,
+        );
+        output.gauge(
+            "redis_uptime_in_seconds",
+            "Seconds since the server started.",
+            self.stats.started.elapsed().as_secs(),
+        );
+    }
+
+    fn write_clients(&self, output: &mut Exposition) {
+        let stats = self.clients.stats();
+        output.gauge(
+            "redis_connected_clients",
+            "Connected clients, excluding replicas.",
+            self.clients.normal_len(),
+        );
+        output.gauge(
+            "redis_config_maxclients",
+            "Maximum number of connected clients.",
+            self.config.maxclients,
+        );
+        output.counter(
+            "redis_connections_received_total",
+            "Connections accepted by the server.",
+            stats.connections_received,
+        );
+        output.counter(
+            "redis_rejected_connections_total",
+            "Connections rejected because of maxclients.",
+            stats.rejected_connections,
+        );
+        output.counter(
+            "redis_net_input_bytes_total",
+            "Bytes read from clients.",
+            stats.net_input_bytes,
+        );
+        output.counter(
+            "redis_net_output_bytes_total",
+            "Bytes written to clients.",
+            stats.net_output_bytes,
+        );
+    }
+
+    fn write_commands(&self, output: &mut Exposition, lookups: &Lookups) {
+        let commands = self.stats.commands();
+
+        output.counter(
+            "redis_commands_processed_total",
+            "Commands processed by the server.",
+            self.stats.total_calls(),
+        );
+        output.family("redis_commands_total", "counter", "Calls per command.");
+        for (name, stats) in &commands {
+            output.sample("redis_commands_total", &[("cmd", name)], stats.calls);
+        }
+        output.family(
+            "redis_commands_duration_seconds_total",
+            "counter",
+            "Time spent per command.",
+        );
+        for (name, stats) in &commands {
+            output.sample(
+                "redis_commands_duration_seconds_total",
+                &[("cmd", name)],
+                stats.usec as f64 / 1e6,
+            );
+        }
+        output.family(
+            "redis_commands_rejected_calls_total",
+            "counter",
+            "Calls per command rejected before they ran.",
+        );
+        for (name, stats) in &commands {
+            output.sample(
+                "redis_commands_rejected_calls_total",
+                &[("cmd", name)],
+                stats.rejected_calls,
+            );
+        }
+        output.family(
+            "redis_commands_failed_calls_total",
+            "counter",
+            "Calls per command that replied with an error.",
+        );
+        for (name, stats) in &commands {
+            output.sample(
+                "redis_commands_failed_calls_total",
+                &[("cmd", name)],
+                stats.failed_calls,
+            );
+        }
+        output.family("redis_errors_total", "counter", "Error replies per code.");
+        for (code, count) in self.stats.errors() {
+            output.sample("redis_errors_total", &[("err", &code)], count);
+        }
+        output.counter(
+            "redis_keyspace_hits_total",
+            "Lookups of keys that existed.",
+            lookups.hits,
+        );
+        output.counter(
+            "redis_keyspace_misses_total",
+            "Lookups of keys that didn't exist.",
+            lookups.misses,
+        );
+    }
+
+    fn write_memory(&self, output: &mut Exposition) {
+        output.gauge(
+            "redis_memory_used_bytes",
+            "Bytes allocated by the server.",
+            allocator::used(),
+        );
+        output.gauge(
+            "redis_memory_used_peak_bytes",
+            "Most bytes allocated by the server at once.",
+            allocator::peak(),
+        );
+        output.gauge(
+            "redis_memory_used_rss_bytes",
+            "Resident set size of the server.",
+            allocator::resident().unwrap_or_default(),
+        );
+        output.gauge(
+            "redis_total_system_memory_bytes",
+            "Physical memory of the machine.",
+            allocator::system_total().unwrap_or_default(),
+        );
+        output.gauge(
+            "redis_memory_max_bytes",
+            "Memory limit of the server, 0 when unlimited.",
+            0,
+        );
+    }
+
+    fn write_replication(&self, output: &mut Exposition) {
+        output.gauge(
+            "redis_master_repl_offset",
+            "Replication offset of the server.",
+            self.state.offset(),
+        );
+
+        if let Some((_, link)) = self.topology.master_link() {
+            output.gauge(
+                "redis_master_link_up",
+                "Whether the link to the master is up.",
+                link.up as u8,
+            );
+            output.gauge(
+                "redis_master_last_io_seconds_ago",
+                "Seconds since the last interaction with the master.",
+                link.last_io.elapsed().as_secs(),
+            );
+            output.gauge(
+                "redis_slave_repl_offset",
+                "Offset of the replication stream processed from the master.",
+                self.state.offset(),
+            );
+        }
+
+        let replicas = self.topology.replicas();
+        output.gauge(
+            "redis_connected_slaves",
+            "Connected replicas.",
+            replicas.len(),
+        );
+        output.counter(
+            "redis_dropped_slaves_total",
+            "Replicas removed because they disconnected or timed out.",
+            self.topology.dropped_replicas(),
+        );
+        output.family(
+            "redis_connected_slave_offset_bytes",
+            "gauge",
+            "Offset acknowledged per replica.",
+        );
+        let labels = replicas
+            .iter()
+            .map(|replica| {
+                (
+                    replica.node.connection_addr().ip().to_string(),
+                    replica.node.addr().port().to_string(),
+                    replica.state.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (replica, (ip, port, state)) in replicas.iter().zip(&labels) {
+            output.sample(
+                "redis_connected_slave_offset_bytes",
+                &[
+                    ("slave_ip", ip),
+                    ("slave_port", port),
+                    ("slave_state", state),
+                ],
+                replica.offset,
+            );
+        }
+        output.family(
+            "redis_connected_slave_lag_seconds",
+            "gauge",
+            "Seconds since the last acknowledgement per replica.",
+        );
+        for (replica, (ip, port, state)) in replicas.iter().zip(&labels) {
+            output.sample(
+                "redis_connected_slave_lag_seconds",
+                &[
+                    ("slave_ip", ip),
+                    ("slave_port", port),
+                    ("slave_state", state),
+                ],
+                replica.lag(),
+            );
+        }
+    }
+
+    fn write_persistence(&self, output: &mut Exposition) {
+        let last_save = self.storage.last_save();
+
+        output.gauge(
+            "redis_loading_dump_file",
+            "Whether a snapshot is being loaded.",
+            0,
+        );
+        output.gauge(
+            "redis_rdb_bgsave_in_progress",
+            "Whether a snapshot is being saved.",
+            0,
+        );
+        output.gauge(
+            "redis_rdb_last_save_timestamp_seconds",
+            "Unix time of the last snapshot saved or loaded, or of the start without one.",
+            last_save
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+        output.gauge(
+            "redis_rdb_last_bgsave_status",
+            "Whether the last attempt to save a snapshot succeeded.",
+            !last_save.failed as u8,
+        );
+        output.gauge("redis_aof_enabled", "Whether the AOF is enabled.", 0);
+    }
+}
+
+fn write_keyspace(output: &mut Exposition, keyspace: &Keyspace) {
+    let db = [("db", "db0")];
+
+    output.family("redis_db_keys", "gauge", "Keys per database.");
+    output.sample("redis_db_keys", &db, keyspace.keys);
+    output.family(
+        "redis_db_keys_expiring",
+        "gauge",
+        "Keys with an expiration per database.",
+    );
+    output.sample("redis_db_keys_expiring", &db, keyspace.expires);
+    output.family(
+        "redis_db_avg_ttl_seconds",
+        "gauge",
+        "Average time to live of the keys with an expiration per database.",
+    );
+    output.sample(
+        "redis_db_avg_ttl_seconds",
+        &db,
+        keyspace.avg_ttl as f64 / 1e3,
+    );
+}
+
+/// Metrics in the Prometheus text format.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.0, "# HELP {name} {help}").unwrap();
+        writeln!(self.0, "# TYPE {name} {kind}").unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            self.0.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.0.push(',');
+                }
+                write!(self.0, "{label}=\"").unwrap();
+                for c in value.chars() {
+                    match c {
+                        '\\' => self.0.push_str("\\\\"),
+                        '"' => self.0.push_str("\\\""),
+                        '\n' => self.0.push_str("\\n"),
+                        c => self.0.push(c),
+                    }
+                }
+                self.0.push('"');
+            }
+            self.0.push('}');
+        }
+        writeln!(self.0, " {value}").unwrap();
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "counter", help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// Serves `GET /metrics` over HTTP, every connection handles a single request.
+pub async fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) -> eyre::Result<()> {
+    loop {
+        let (connection, addr) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(error) = respond(connection, &metrics).await {
+                tracing::debug!(%addr, ?error, "Metrics request failed");
+            }
+        });
+    }
+}
+
+async fn respond(mut connection: TcpStream, metrics: &Metrics) -> eyre::Result<()> {
+    let head = timeout(REQUEST_TIMEOUT, read_head(&mut connection)).await??;
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "Not Found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_owned()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    connection.write_all(response.as_bytes()).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
+
+/// Reads the request line and headers.
+async fn read_head(connection: &mut TcpStream) -> eyre::Result<String> {
+    let mut head = Vec::with_capacity(1024);
+    while !head.windows(4).any(|it| it == b"\r\n\r\n") {
+        if head.len() >= MAX_REQUEST_SIZE || connection.read_buf(&mut head).await? == 0 {
+            eyre::bail!("Incomplete request");
+        }
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_escapes_label_values() {
+        let mut output = Exposition::default();
+        output.sample("redis_errors_total", &[("err", "a\\b \"c\"\nd")], 1);
+        assert_eq!(
+            output.0,
+            "redis_errors_total{err=\"a\\\\b \\\"c\\\"\\nd\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn family_declares_help_and_type() {
+        let mut output = Exposition::default();
+        output.counter("redis_up_total", "Whether it's up.", 1);
+        output.sample("redis_db_keys", &[("db", "db0"), ("role", "master")], 2.5);
+        assert_eq!(
+            output.0,
+            "# HELP redis_up_total Whether it's up.\n# TYPE redis_up_total counter\nredis_up_total 1\n\
+             redis_db_keys{db=\"db0\",role=\"master\"} 2.5\n"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fmt::Write,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use tokio::sync::broadcast;
+use tower::{Layer, Service};
+
+use crate::{
+    error::RedisError,
+    request::Request,
+    response::Response,
+    routing::{CommandFlag, CommandTable},
+};
+
+/// Lines a monitor can fall behind before it's disconnected.
+const FEED_CAPACITY: usize = 4096;
+
+pub type SharedMonitors = Arc<Monitors>;
+
+/// Feed of the lines of `MONITOR`, one per processed command.
+pub type MonitorFeed = broadcast::Receiver<Bytes>;
+
+/// Connections in `MONITOR` mode.
+pub struct Monitors {
+    feed: broadcast::Sender<Bytes>,
+}
+
+impl Default for Monitors {
+    fn default() -> Self {
+        Self {
+            feed: broadcast::channel(FEED_CAPACITY).0,
+        }
+    }
+}
+
+impl Monitors {
+    pub fn subscribe(&self) -> MonitorFeed {
+        self.feed.subscribe()
+    }
+
+    fn is_watched(&self) -> bool {
+        self.feed.receiver_count() > 0
+    }
+
+    fn publish(&self, line: Bytes) {
+        // no monitor left since the line was formatted
+        let _ = self.feed.send(line);
+    }
+}
+
+/// Line of the feed, like `+1339518083.107412 [0 127.0.0.1:60866] "keys" "*"`.
+fn feed_line(request: &Request, time: SystemTime) -> Bytes {
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!(
+        "+{}.{:06} [0 {}]",
+        time.as_secs(),
+        time.subsec_micros(),
+        request.state().addr()
+    );
+    for arg in request.redacted_argv() {
+        line.push(' ');
+        quote(&mut line, arg);
+    }
+    line.push_str("\r\n");
+    Bytes::from(line)
+}
+
+/// Quotes an argument the way `redis-cli` would print it, escaping what isn't printable.
+fn quote(output: &mut String, arg: &str) {
+    output.push('"');
+    for byte in arg.bytes() {
+        match byte {
+            b'\\' => output.push_str("\\\\"),
+            b'"' => output.push_str("\\\""),
+            b'\n' => output.push_str("\\n"),
+            b'\r' => output.push_str("\\r"),
+            b'\t' => output.push_str("\\t"),
+            0x07 => output.push_str("\\a"),
+            0x08 => output.push_str("\\b"),
+            b' '..=b'~' => output.push(byte as char),
+            _ => write!(output, "\\x{byte:02x}").unwrap(),
+        }
+    }
+    output.push('"');
+}
+
+/// Feeds the calls of the routes it's applied to to the [`Monitors`].
+#[derive(Clone)]
+pub struct MonitorLayer(pub SharedMonitors);
+
+impl<S> Layer<S> for MonitorLayer {
+    type Service = FeedMonitors<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FeedMonitors {
+            inner,
+            monitors: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FeedMonitors<S> {
+    inner: S,
+    monitors: SharedMonitors,
+}
+
+impl<S> Service<Request> for FeedMonitors<S>
+where
+    S: Service<Request, Response = Response, Error = RedisError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = RedisError;
+    type Future = BoxFuture<'static, Result<Response, RedisError>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if !self.monitors.is_watched() {
+            return Box::pin(self.inner.call(req));
+        }
+        // like redis, administrative commands are never shown, and neither are unknown ones
+        let shown = req
+            .extensions()
+            .get::<CommandTable>()
+            .and_then(|it| it.get(&req.command))
+            .is_some_and(|it| !it.has_flag(CommandFlag::Admin));
+        let line = shown.then(|| feed_line(&req, SystemTime::now()));
+        let monitors = self.monitors.clone();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            // rejected calls were never executed
+            let rejected = result.as_ref().is_err_and(|it| it.is_rejection());
+            if let (Some(line), false) = (line, rejected) {
+                monitors.publish(line);
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use super::*;
+    use crate::state::ConnectionState;
+
+    fn request(argv: &[&str]) -> Request {
+        let state = ConnectionState::new(SocketAddr::from(([127, 0, 0, 1], 60866)).into());
+        Request::from_command_line(argv.iter().map(|it| it.to_string()).collect(), state).unwrap()
+    }
+
+    fn line(argv: &[&str]) -> String {
+        let time = UNIX_EPOCH + Duration::from_micros(1_339_518_083_107_412);
+        String::from_utf8(feed_line(&request(argv), time).to_vec()).unwrap()
+    }
+
+    #[test]
+    fn feed_line_quotes_every_argument() {
+        assert_eq!(
+            line(&["KEYS", "*"]),
+            "+1339518083.107412 [0 127.0.0.1:60866] \"keys\" \"*\"\r\n"
+        );
+    }
+
+    #[test]
+    fn quote_escapes_what_is_not_printable() {
+        let mut output = String::new();
+        quote(&mut output, "a \"b\"\\\r\n\t\x07\x08\x01é");
+        assert_eq!(output, r#""a \"b\"\\\r\n\t\a\b\x01\xc3\xa9""#);
+    }
+
+    #[test]
+    fn feed_line_redacts_credentials() {
+        assert!(line(&["AUTH", "default", "secret"])
+            .ends_with("\"auth\" \"(redacted)\" \"(redacted)\"\r\n"));
+        assert!(
+            line(&["HELLO", "3", "AUTH", "default", "secret", "SETNAME", "app"]).ends_with(
+                "\"hello\" \"3\" \"AUTH\" \"(redacted)\" \"(redacted)\" \"SETNAME\" \"app\"\r\n"
+            )
+        );
+        assert!(line(&["HELLO", "3"]).ends_with("\"hello\" \"3\"\r\n"));
+    }
+}
@@ -1,27 +1,36 @@
 mod connection;
+mod tls;
 mod transport;
 
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
 use bytes::{Buf, Bytes, BytesMut};
 use eyre::WrapErr;
-use futures_util::{stream::FuturesUnordered, StreamExt};
+use futures_util::{
+    stream::{BoxStream, FuturesUnordered},
+    FutureExt, StreamExt,
+};
 use nom::{AsBytes, FindSubstring};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
+    time::timeout,
 };
+use tokio_rustls::TlsStream;
 use tracing::instrument;
 
 pub use self::{
     connection::Connection,
-    transport::{Listener, Transport},
+    tls::{TlsAddr, TlsContext, TlsListener},
+    transport::{Link, Listener, PeerAddr, Transport},
 };
 use crate::{
+    config::OutputBufferLimit,
+    encoding::Protocol,
     error::{RedisError, RedisError::ResponseFailed},
     replication::{NodeRole, OffsetId, ReplicationId},
-    response::Response,
+    response::{OutputBuffer, Response},
 };
 
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq, Eq, Hash)]
@@ -56,6 +65,10 @@ impl NodeId {
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
+
+    pub fn connection_addr(&self) -> SocketAddr {
+        self.connection_addr
+    }
 }
 
 pub trait Network {
@@ -66,17 +79,34 @@ pub trait Network {
         &mut self,
         target: &NodeId,
     ) -> Result<(T, usize), RedisError>;
-    async fn broadcast<T: Serialize>(&mut self, data: &T) -> Result<usize, RedisError>;
+    /// Same as [`Network::receive`], but also returns the exact bytes of the received message.
+    async fn receive_frame<T: DeserializeOwned>(
+        &mut self,
+        target: &NodeId,
+    ) -> Result<(T, Bytes), RedisError>;
+    /// Sends `data` to every connected node, returning the number of bytes sent
+    /// and nodes that failed to receive it. Failed nodes are disconnected.
+    async fn broadcast<T: Serialize>(
+        &mut self,
+        data: &T,
+    ) -> Result<(usize, Vec<NodeId>), RedisError>;
+    async fn broadcast_raw(&mut self, data: Bytes) -> Result<(usize, Vec<NodeId>), RedisError>;
 }
 
+/// Length of the marker delimiting RDB in diskless replication.
+const RDB_EOF_MARKER_SIZE: usize = 40;
+
 pub trait NetworkExt: Network {
     async fn respond(&mut self, node: &NodeId, response: Response) -> Result<(), RedisError> {
         match response {
             Response::Raw(data) => {
                 self.send_raw(node, data).await?;
             }
+            Response::Value(value) => {
+                self.send_raw(node, value.encode(Protocol::Resp2)).await?;
+            }
             Response::Empty => {}
-            Response::Upgrade { .. } => {}
+            Response::Upgrade { .. } | Response::Monitor(_) => {}
         }
         Ok(())
     }
@@ -144,46 +174,172 @@ pub trait NetworkExt: Network {
         self.send_raw(target, data).await?;
         Ok(())
     }
+
+    /// Streams RDB to all targets as it's generated, using EOF marker framing
+    /// since the size is not known upfront. Returns targets that failed to receive it,
+    /// which is all of them when the RDB can't be generated.
+    async fn send_rdb_diskless(
+        &mut self,
+        targets: &[NodeId],
+        rdb: BoxStream<'static, Result<Bytes, RedisError>>,
+    ) -> Vec<NodeId> {
+        let mut marker = [0u8; RDB_EOF_MARKER_SIZE];
+        hex::encode_to_slice(rand::random::<[u8; RDB_EOF_MARKER_SIZE / 2]>(), &mut marker).unwrap();
+        let marker = Bytes::copy_from_slice(&marker);
+
+        let mut prefix = BytesMut::from(&b"$EOF:"[..]);
+        prefix.extend_from_slice(&marker);
+        prefix.extend_from_slice(b"\r\n");
+        let prefix = prefix.freeze();
+
+        let mut chunks = futures_util::stream::iter([Ok(prefix)])
+            .chain(rdb)
+            .chain(futures_util::stream::iter([Ok(marker)]));
+
+        let mut failed = vec![];
+        while let Some(chunk) = chunks.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    // replicas can't tell a truncated snapshot from a complete one
+                    tracing::error!(%error, "Failed to generate snapshot for replicas");
+                    return targets.to_vec();
+                }
+            };
+            for target in targets {
+                if failed.contains(target) {
+                    continue;
+                }
+                if self.send_raw(target, chunk.clone()).await.is_err() {
+                    failed.push(*target);
+                }
+            }
+        }
+
+        failed
+    }
 }
 
 impl<T: Network> NetworkExt for T {}
 
 pub struct RedisNetwork {
     connections: HashMap<NodeId, OpenedConnection>,
+    /// Connections opened to other nodes are encrypted when set.
+    tls: Option<TlsContext>,
 }
 
 impl RedisNetwork {
-    pub async fn new(init: impl IntoIterator<Item = NodeId>) -> eyre::Result<Self> {
+    pub async fn new(
+        init: impl IntoIterator<Item = NodeId>,
+        tls: Option<TlsContext>,
+    ) -> eyre::Result<Self> {
         let mut connections = HashMap::new();
         for node in init {
-            let connection = OpenedConnection::open(&node).await?;
+            let connection = OpenedConnection::open(&node, tls.as_ref()).await?;
             connections.insert(node, connection);
         }
 
-        Ok(Self { connections })
+        Ok(Self { connections, tls })
     }
 
+    /// Adds the link of a replica, the replication stream sent to it is subject to `limit`
+    /// and other writes, like the snapshot, fail when they make no progress for `write_timeout`.
     pub(crate) fn add_connection(
         &mut self,
         target: &NodeId,
-        stream: TcpStream,
+        stream: Link,
+        limit: OutputBufferLimit,
+        write_timeout: Duration,
     ) -> eyre::Result<()> {
         self.connections.insert(
             target.clone(),
             OpenedConnection {
                 stream,
                 buf: BytesMut::new(),
+                output: OutputBuffer::new(limit),
+                write_timeout: Some(write_timeout),
             },
         );
 
         Ok(())
     }
 
+    pub(crate) fn remove_connection(&mut self, target: &NodeId) -> bool {
+        self.connections.remove(target).is_some()
+    }
+
+    /// Nodes whose connections were closed by the peer.
+    pub(crate) fn disconnected(&mut self) -> Vec<NodeId> {
+        self.connections
+            .iter_mut()
+            .filter_map(|(node, connection)| connection.is_closed().then_some(*node))
+            .collect()
+    }
+
+    /// Waits until the replication stream buffered for some node is partially written,
+    /// returning nodes that failed and were disconnected. Never completes when nothing is buffered.
+    pub(crate) async fn write_pending(&mut self) -> Vec<NodeId> {
+        let mut writes = self
+            .connections
+            .iter_mut()
+            .filter(|(_, connection)| connection.output.is_pending())
+            .map(|(node, connection)| async move {
+                let OpenedConnection { stream, output, .. } = connection;
+                (*node, output.write_some(stream).await)
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let Some((node, result)) = writes.next().await else {
+            drop(writes);
+            return std::future::pending().await;
+        };
+        drop(writes);
+
+        match result {
+            Ok(_) => vec![],
+            Err(error) => {
+                tracing::warn!(?node, %error, "Failed to write replication stream to node");
+                self.connections.remove(&node);
+                vec![node]
+            }
+        }
+    }
+
+    /// Disconnects nodes whose buffered replication stream stayed above the soft limit
+    /// for too long, even though nothing was added to it since.
+    pub(crate) fn check_output_limits(&mut self) -> Vec<NodeId> {
+        let failed = self
+            .connections
+            .iter_mut()
+            .filter_map(
+                |(node, connection)| match connection.output.check_soft_limit() {
+                    Ok(_) => None,
+                    Err(error) => {
+                        tracing::warn!(?node, %error, "Replication stream limit reached");
+                        Some(*node)
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        for node in &failed {
+            self.connections.remove(node);
+        }
+        failed
+    }
+
+    /// Parses a message that is already buffered for the node, without reading the socket.
+    pub(crate) fn try_receive<T: DeserializeOwned>(
+        &mut self,
+        target: &NodeId,
+    ) -> Option<(T, usize)> {
+        self.connections.get_mut(target)?.try_receive()
+    }
+
     async fn get_connection(&mut self, target: &NodeId) -> eyre::Result<&mut OpenedConnection> {
         if self.connections.contains_key(target) {
             return Ok(self.connections.get_mut(target).unwrap());
         }
-        let new_connection = OpenedConnection::open(target).await?;
+        let new_connection = OpenedConnection::open(target, self.tls.as_ref()).await?;
 
         Ok(self
             .connections
@@ -222,52 +378,119 @@ impl Network for RedisNetwork {
         connection.receive().await
     }
 
+    #[instrument(skip(self), err)]
+    async fn receive_frame<T: DeserializeOwned>(
+        &mut self,
+        target: &NodeId,
+    ) -> Result<(T, Bytes), RedisError> {
+        let connection = self.get_connection(target).await?;
+
+        connection.receive_frame().await
+    }
+
     #[instrument(skip(self, data), ret, err)]
-    async fn broadcast<T: Serialize>(&mut self, data: &T) -> Result<usize, RedisError> {
-        let mut f = FuturesUnordered::new();
+    async fn broadcast<T: Serialize>(
+        &mut self,
+        data: &T,
+    ) -> Result<(usize, Vec<NodeId>), RedisError> {
         let data = crate::encoding::resp2::to_bytes(data).unwrap();
-        let sent_bytes = data.len();
-
-        for (node, mut connection) in self.connections.drain() {
-            let data = data.clone();
-            f.push(async move {
-                let result = connection.request_raw(data).await;
+        self.broadcast_raw(data).await
+    }
 
-                (node, connection, result)
-            });
+    /// Never waits for nodes to read, data they don't accept right away stays buffered
+    /// until [`RedisNetwork::write_pending`] writes it.
+    #[instrument(skip(self, data), ret, err)]
+    async fn broadcast_raw(&mut self, data: Bytes) -> Result<(usize, Vec<NodeId>), RedisError> {
+        let mut failed = vec![];
+        for (node, connection) in self.connections.iter_mut() {
+            if let Err(error) = connection.stream_raw(&data) {
+                tracing::warn!(?node, %error, "Failed to broadcast to node");
+                failed.push(*node);
+            }
         }
-        let mut error = None;
-
-        while let Some((node, connection, result)) = f.next().await {
-            self.connections.insert(node, connection);
-            error = error.or(result.err());
+        for node in &failed {
+            self.connections.remove(node);
         }
 
-        Ok(sent_bytes)
+        Ok((data.len(), failed))
     }
 }
 
+enum RdbSize {
+    Exact(usize),
+    UntilMarker(Bytes),
+}
+
 struct OpenedConnection {
-    stream: TcpStream,
+    stream: Link,
     buf: BytesMut,
+    /// Replication stream waiting to be written, snapshots aren't counted like in redis.
+    output: OutputBuffer,
+    write_timeout: Option<Duration>,
 }
 
 impl OpenedConnection {
-    async fn open(node: &NodeId) -> eyre::Result<Self> {
-        let stream = TcpStream::connect(&node.addr)
-            .await
-            .wrap_err("Connection to node")?;
+    async fn open(node: &NodeId, tls: Option<&TlsContext>) -> eyre::Result<Self> {
+        let stream: Link = match tls {
+            Some(context) => Box::new(
+                <TlsStream<TcpStream> as Transport>::connect(&TlsAddr {
+                    addr: node.addr,
+                    context: context.clone(),
+                })
+                .await
+                .wrap_err("Connection to node")?,
+            ),
+            None => Box::new(
+                TcpStream::connect(&node.addr)
+                    .await
+                    .wrap_err("Connection to node")?,
+            ),
+        };
 
         Ok(Self {
             stream,
             buf: BytesMut::new(),
+            output: OutputBuffer::new(OutputBufferLimit::default()),
+            write_timeout: None,
         })
     }
 
-    #[instrument(skip(self), err)]
+    /// Checks without blocking whether the peer has closed the connection.
+    /// Any data that is already available is kept in the buffer.
+    fn is_closed(&mut self) -> bool {
+        match self.stream.read_buf(&mut self.buf).now_or_never() {
+            None => false,
+            Some(Ok(0)) => true,
+            Some(Ok(_)) => false,
+            Some(Err(_)) => true,
+        }
+    }
+
+    #[instrument(skip(self, buffer), err)]
     async fn request_raw(&mut self, buffer: Bytes) -> Result<(), RedisError> {
-        tracing::debug!(?buffer, "Sending request");
-        self.stream.write_all(buffer.as_bytes()).await?;
+        tracing::debug!(size = buffer.len(), "Sending request");
+        let OpenedConnection { stream, output, .. } = self;
+        // buffered replication stream goes first to keep the order
+        let write = async move {
+            output.flush(stream).await?;
+            stream.write_all(buffer.as_bytes()).await?;
+            Ok::<_, RedisError>(())
+        };
+
+        match self.write_timeout {
+            Some(limit) => timeout(limit, write)
+                .await
+                .map_err(|_| eyre::eyre!("Write made no progress for {limit:?}"))?,
+            None => write.await,
+        }
+    }
+
+    /// Buffers part of the replication stream and writes what the node accepts without blocking,
+    /// failing once the output buffer limits are hit.
+    fn stream_raw(&mut self, buffer: &[u8]) -> Result<(), RedisError> {
+        tracing::debug!(size = buffer.len(), "Streaming to node");
+        self.output.push_raw(buffer)?;
+        self.output.write_available(&mut self.stream)?;
         Ok(())
     }
 
@@ -277,12 +500,14 @@ impl OpenedConnection {
         self.request_raw(buffer).await
     }
 
+    /// Receives RDB sent either with its length `$<len>\r\n<rdb>` or, for diskless
+    /// replication, delimited by a marker `$EOF:<marker>\r\n<rdb><marker>`.
     #[instrument(skip(self), err)]
     async fn receive_rdb(&mut self) -> Result<Bytes, RedisError> {
         let OpenedConnection { stream, buf, .. } = self;
 
         // size loop
-        let size: usize = loop {
+        let size: RdbSize = loop {
             if let Some(pos) = buf.as_bytes().find_substring("\r\n") {
                 let prefix = buf.split_to(pos + 2);
                 let len = prefix
@@ -290,9 +515,17 @@ impl OpenedConnection {
                     .ok_or_else(|| ResponseFailed)?
                     .strip_suffix(b"\r\n")
                     .unwrap();
+
+                if let Some(marker) = len.strip_prefix(b"EOF:") {
+                    if marker.len() != RDB_EOF_MARKER_SIZE {
+                        return Err(ResponseFailed);
+                    }
+                    break RdbSize::UntilMarker(Bytes::copy_from_slice(marker));
+                }
+
                 let len = std::str::from_utf8(len).map_err(|_| ResponseFailed)?;
 
-                break len.parse().map_err(|_| ResponseFailed)?;
+                break RdbSize::Exact(len.parse().map_err(|_| ResponseFailed)?);
             }
 
             let read = stream.read_buf(buf).await.map_err(|_| ResponseFailed)?;
@@ -301,8 +534,33 @@ impl OpenedConnection {
                 return Err(ResponseFailed);
             }
         };
-        tracing::trace!("Expecting {size} to receive bytes");
-        buf.reserve(size);
+
+        let size = match size {
+            RdbSize::Exact(size) => {
+                tracing::trace!("Expecting {size} to receive bytes");
+                buf.reserve(size);
+                size
+            }
+            RdbSize::UntilMarker(marker) => {
+                tracing::trace!(?marker, "Expecting to receive bytes until marker");
+                let mut searched = 0;
+                loop {
+                    if let Some(pos) = (&buf[searched..]).find_substring(marker.as_bytes()) {
+                        let rdb = buf.split_to(searched + pos).freeze();
+                        buf.advance(marker.len());
+                        return Ok(rdb);
+                    }
+                    // marker could be split between reads
+                    searched = buf.len().saturating_sub(marker.len() - 1);
+
+                    let read = stream.read_buf(buf).await.map_err(|_| ResponseFailed)?;
+                    if read == 0 {
+                        tracing::trace!("EOF too early");
+                        return Err(ResponseFailed);
+                    }
+                }
+            }
+        };
 
         loop {
             if buf.len() >= size {
@@ -319,13 +577,24 @@ impl OpenedConnection {
         Ok(buf.split_to(size).freeze())
     }
 
+    fn try_receive<T: DeserializeOwned>(&mut self) -> Option<(T, usize)> {
+        let (result, read) = crate::encoding::resp2::from_bytes(self.buf.as_bytes()).ok()?;
+        self.buf.advance(read);
+        Some((result, read))
+    }
+
     #[instrument(skip(self), err)]
     async fn receive<T: DeserializeOwned>(&mut self) -> Result<(T, usize), RedisError> {
+        let (result, frame) = self.receive_frame().await?;
+        Ok((result, frame.len()))
+    }
+
+    #[instrument(skip(self), err)]
+    async fn receive_frame<T: DeserializeOwned>(&mut self) -> Result<(T, Bytes), RedisError> {
         let OpenedConnection { stream, buf, .. } = self;
         loop {
             if let Ok((result, read)) = crate::encoding::resp2::from_bytes(buf.as_bytes()) {
-                buf.advance(read);
-                return Ok((result, read));
+                return Ok((result, buf.split_to(read).freeze()));
             };
 
             let read = stream
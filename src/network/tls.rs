@@ -0,0 +1,357 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use eyre::{eyre, Context};
+use nom::AsBytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    select,
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+        server::WebPkiClientVerifier,
+        ClientConfig, RootCertStore, ServerConfig,
+    },
+    TlsAcceptor, TlsConnector, TlsStream,
+};
+
+use super::{transport::set_tcp_keepalive, Link, Listener, PeerAddr, Transport};
+use crate::config::{Config, TlsAuthClients};
+
+/// Clients that don't finish the handshake in time are disconnected.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificates used both to accept TLS clients and to connect to other nodes.
+#[derive(Clone)]
+pub struct TlsContext {
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+}
+
+impl TlsContext {
+    /// Loads certificates from `tls-*` options, `None` when TLS isn't configured.
+    pub fn load(config: &Config) -> eyre::Result<Option<Self>> {
+        let (Some(cert_file), Some(key_file)) = (&config.tls_cert_file, &config.tls_key_file)
+        else {
+            if config.tls_port != 0 || config.tls_replication {
+                return Err(eyre!("tls-cert-file and tls-key-file are required for TLS"));
+            }
+            return Ok(None);
+        };
+
+        let certs = load_certs(cert_file)?;
+        let key = load_key(key_file)?;
+        let roots = match &config.tls_ca_cert_file {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(path)? {
+                    roots.add(cert).wrap_err("Invalid CA certificate")?;
+                }
+                Some(Arc::new(roots))
+            }
+            None => None,
+        };
+        if config.tls_replication && roots.is_none() {
+            return Err(eyre!(
+                "tls-ca-cert-file is required to verify other nodes with tls-replication"
+            ));
+        }
+
+        let server = ServerConfig::builder();
+        let server = match (config.tls_auth_clients, &roots) {
+            (TlsAuthClients::No, _) => server.with_no_client_auth(),
+            (auth, Some(roots)) => {
+                let verifier = WebPkiClientVerifier::builder(roots.clone());
+                let verifier = match auth {
+                    TlsAuthClients::Optional => verifier.allow_unauthenticated(),
+                    _ => verifier,
+                };
+                server.with_client_cert_verifier(verifier.build()?)
+            }
+            (_, None) => {
+                return Err(eyre!(
+                    "tls-ca-cert-file is required to authenticate clients, or set tls-auth-clients to no"
+                ))
+            }
+        }
+        .with_single_cert(certs.clone(), key.clone_key())
+        .wrap_err("Invalid certificate or key")?;
+
+        // nodes authenticate to each other with the same certificate, without a CA the
+        // connector is never used since only replication links are opened over TLS
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots.unwrap_or_else(|| Arc::new(RootCertStore::empty())))
+            .with_client_auth_cert(certs, key)
+            .wrap_err("Invalid certificate or key")?;
+
+        Ok(Some(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server)),
+            connector: TlsConnector::from(Arc::new(client)),
+        }))
+    }
+}
+
+fn load_certs(path: &Path) -> eyre::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(
+        File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?,
+    );
+    rustls_pemfile::certs(&mut reader)
+        .collect::<Result<_, _>>()
+        .wrap_err_with(|| format!("Failed to read certificates from {}", path.display()))
+}
+
+fn load_key(path: &Path) -> eyre::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(
+        File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?,
+    );
+    rustls_pemfile::private_key(&mut reader)
+        .wrap_err_with(|| format!("Failed to read private key from {}", path.display()))?
+        .ok_or_else(|| eyre!("No private key in {}", path.display()))
+}
+
+/// Address of a TLS endpoint, with the certificates used on it.
+#[derive(Clone)]
+pub struct TlsAddr {
+    pub addr: SocketAddr,
+    pub context: TlsContext,
+}
+
+impl From<TlsAddr> for PeerAddr {
+    fn from(value: TlsAddr) -> Self {
+        Self::Tcp(value.addr)
+    }
+}
+
+impl Transport for TlsStream<TcpStream> {
+    type Address = TlsAddr;
+    type Listener = TlsListener;
+
+    async fn bind(address: &Self::Address) -> eyre::Result<Self::Listener> {
+        let listener = TcpListener::bind(address.addr).await.wrap_err("bind")?;
+
+        Ok(TlsListener {
+            listener,
+            context: address.context.clone(),
+            handshakes: JoinSet::new(),
+        })
+    }
+
+    async fn connect(address: &Self::Address) -> eyre::Result<Self> {
+        let stream = TcpStream::connect(address.addr)
+            .await
+            .wrap_err("Could not connect")?;
+        let stream = address
+            .context
+            .connector
+            .connect(ServerName::from(address.addr.ip()), stream)
+            .await
+            .wrap_err("TLS handshake failed")?;
+
+        Ok(stream.into())
+    }
+
+    async fn write(&mut self, buffer: Bytes) -> eyre::Result<()> {
+        self.write_all(buffer.as_bytes())
+            .await
+            .wrap_err("writing to socket")?;
+        Ok(())
+    }
+
+    async fn read(&mut self, buffer: &mut BytesMut) -> eyre::Result<usize> {
+        let size = self
+            .read_buf(buffer)
+            .await
+            .wrap_err("reading from socket")?;
+        Ok(size)
+    }
+
+    fn set_keepalive(&self, idle: Duration) -> io::Result<()> {
+        set_tcp_keepalive(self.get_ref().0, idle)
+    }
+
+    fn into_link(self) -> Result<Link, Self> {
+        Ok(Box::new(self))
+    }
+}
+
+type Handshake = (SocketAddr, io::Result<TlsStream<TcpStream>>);
+
+/// Accepts TCP connections and runs their handshakes in the background,
+/// so a slow client doesn't hold up the others.
+pub struct TlsListener {
+    listener: TcpListener,
+    context: TlsContext,
+    handshakes: JoinSet<Handshake>,
+}
+
+impl TlsListener {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Listener for TlsListener {
+    type Transport = TlsStream<TcpStream>;
+
+    async fn accept(
+        &mut self,
+    ) -> eyre::Result<(Self::Transport, <Self::Transport as Transport>::Address)> {
+        loop {
+            select! {
+                accepted = self.listener.accept() => {
+                    let (stream, addr) = accepted.wrap_err("accepting connection")?;
+                    let acceptor = self.context.acceptor.clone();
+                    self.handshakes.spawn(async move {
+                        let result = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                            Ok(result) => result.map(TlsStream::from),
+                            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+                        };
+                        (addr, result)
+                    });
+                }
+                Some(handshake) = self.handshakes.join_next() => {
+                    match handshake {
+                        Ok((addr, Ok(stream))) => {
+                            let context = self.context.clone();
+                            return Ok((stream, TlsAddr { addr, context }));
+                        }
+                        Ok((addr, Err(error))) => {
+                            tracing::warn!(%addr, %error, "TLS handshake failed");
+                        }
+                        Err(error) => tracing::warn!(%error, "TLS handshake panicked"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::{LogFormat, LogLevel, OutputBufferLimits};
+
+    /// Certificates of `testdata`, `node.pem` is signed by `ca.pem` for `127.0.0.1` while
+    /// `untrusted.pem` is self-signed.
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/network/testdata")
+            .join(name)
+    }
+
+    fn config(cert: &str, ca: Option<&str>, tls_replication: bool) -> Config {
+        Config {
+            port: 0,
+            bind: Vec::new(),
+            unixsocket: None,
+            unixsocketperm: None,
+            tls_port: 0,
+            tls_cert_file: Some(testdata(&format!("{cert}.pem"))),
+            tls_key_file: Some(testdata(&format!("{cert}-key.pem"))),
+            tls_ca_cert_file: ca.map(testdata),
+            tls_auth_clients: if ca.is_some() {
+                TlsAuthClients::Yes
+            } else {
+                TlsAuthClients::No
+            },
+            tls_replication,
+            timeout: Duration::ZERO,
+            tcp_keepalive: Duration::ZERO,
+            maxclients: 0,
+            dir: None,
+            dbfilename: None,
+            repl_timeout: Duration::ZERO,
+            min_replicas_to_write: 0,
+            min_replicas_max_lag: Duration::ZERO,
+            repl_diskless_sync: false,
+            client_query_buffer_limit: 0,
+            proto_max_bulk_len: 0,
+            client_output_buffer_limit: OutputBufferLimits::default(),
+            shutdown_timeout: Duration::ZERO,
+            slowlog_log_slower_than: None,
+            slowlog_max_len: 0,
+            latency_monitor_threshold: Duration::ZERO,
+            metrics_port: 0,
+            loglevel: LogLevel::default(),
+            logfile: None,
+            log_format: LogFormat::default(),
+        }
+    }
+
+    fn context(config: &Config) -> TlsContext {
+        TlsContext::load(config).unwrap().unwrap()
+    }
+
+    async fn listen(context: &TlsContext) -> (TlsListener, TlsAddr) {
+        let listener = TlsStream::<TcpStream>::bind(&TlsAddr {
+            addr: ([127, 0, 0, 1], 0).into(),
+            context: context.clone(),
+        })
+        .await
+        .unwrap();
+        let addr = listener.local_addr().unwrap();
+        (
+            listener,
+            TlsAddr {
+                addr,
+                context: context.clone(),
+            },
+        )
+    }
+
+    #[test]
+    fn load_requires_ca_for_tls_replication() {
+        assert!(TlsContext::load(&config("node", None, true)).is_err());
+        assert!(TlsContext::load(&config("node", None, false))
+            .unwrap()
+            .is_some());
+        assert!(TlsContext::load(&config("node", Some("ca.pem"), true))
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn nodes_signed_by_the_ca_complete_the_handshake() {
+        let context = context(&config("node", Some("ca.pem"), true));
+        let (mut listener, addr) = listen(&context).await;
+
+        let (accepted, connected) =
+            tokio::join!(listener.accept(), TlsStream::<TcpStream>::connect(&addr));
+        let (mut server, _) = accepted.unwrap();
+        let mut client = connected.unwrap();
+
+        client.write_all(b"PING").await.unwrap();
+        let mut buffer = BytesMut::new();
+        while buffer.len() < 4 {
+            server.read_buf(&mut buffer).await.unwrap();
+        }
+        assert_eq!(&buffer[..], b"PING");
+    }
+
+    #[tokio::test]
+    async fn untrusted_certificates_fail_the_handshake() {
+        let (mut listener, addr) = listen(&context(&config("node", Some("ca.pem"), true))).await;
+        let addr = TlsAddr {
+            addr: addr.addr,
+            context: context(&config("untrusted", Some("untrusted.pem"), true)),
+        };
+
+        select! {
+            _ = listener.accept() => panic!("untrusted node was accepted"),
+            connected = TlsStream::<TcpStream>::connect(&addr) => assert!(connected.is_err()),
+        }
+    }
+}
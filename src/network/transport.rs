@@ -1,21 +1,62 @@
-use std::future::Future;
+use std::{future::Future, io, net::SocketAddr, path::PathBuf, time::Duration};
 
 use bytes::{Bytes, BytesMut};
+use derive_more::Display;
 use eyre::Context;
 use nom::AsBytes;
+use socket2::{SockRef, TcpKeepalive};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
 };
 
-pub trait Transport: Sized + Send + Sync + 'static {
-    type Address;
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Sized + Send + Sync + 'static {
+    type Address: Into<PeerAddr> + Send;
     type Listener: Listener<Transport = Self>;
     async fn bind(address: &Self::Address) -> eyre::Result<Self::Listener>;
     async fn connect(address: &Self::Address) -> eyre::Result<Self>;
 
     async fn write(&mut self, buffer: Bytes) -> eyre::Result<()>;
     async fn read(&mut self, buffer: &mut BytesMut) -> eyre::Result<usize>;
+
+    /// Enables TCP keepalive probes after `idle`, transports that aren't TCP ignore it.
+    fn set_keepalive(&self, _idle: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Replication streams are only sent over TCP or TLS, so other transports can't become replicas.
+    fn into_link(self) -> Result<Link, Self> {
+        Err(self)
+    }
+}
+
+/// Stream of a replication link between nodes.
+pub trait LinkStream: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static> LinkStream for T {}
+
+pub type Link = Box<dyn LinkStream>;
+
+/// Address of a connected client.
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum PeerAddr {
+    #[display(fmt = "{_0}")]
+    Tcp(SocketAddr),
+    /// Clients connected to a unix socket are identified by the socket path.
+    #[display(fmt = "{}:0", "_0.display()")]
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(value: SocketAddr) -> Self {
+        Self::Tcp(value)
+    }
+}
+
+impl From<PathBuf> for PeerAddr {
+    fn from(value: PathBuf) -> Self {
+        Self::Unix(value)
+    }
 }
 
 pub trait Listener: Sized + Send + Sync + 'static {
@@ -28,7 +69,7 @@ pub trait Listener: Sized + Send + Sync + 'static {
 }
 
 impl Transport for TcpStream {
-    type Address = String;
+    type Address = SocketAddr;
     type Listener = TcpListener;
 
     async fn bind(address: &Self::Address) -> eyre::Result<Self::Listener> {
@@ -53,6 +94,23 @@ impl Transport for TcpStream {
             .wrap_err("reading from socket")?;
         Ok(size)
     }
+
+    fn set_keepalive(&self, idle: Duration) -> io::Result<()> {
+        set_tcp_keepalive(self, idle)
+    }
+
+    fn into_link(self) -> Result<Link, Self> {
+        Ok(Box::new(self))
+    }
+}
+
+/// Probes are sent every third of `idle`, so dead peers are found after about twice `idle`.
+pub(super) fn set_tcp_keepalive(stream: &TcpStream, idle: Duration) -> io::Result<()> {
+    let keepalive = TcpKeepalive::new()
+        .with_time(idle)
+        .with_interval(idle / 3)
+        .with_retries(3);
+    SockRef::from(stream).set_tcp_keepalive(&keepalive)
 }
 
 impl Listener for TcpListener {
@@ -65,6 +123,60 @@ impl Listener for TcpListener {
             .await
             .wrap_err("accepting connection")?;
 
-        Ok((s, a.to_string()))
+        Ok((s, a))
+    }
+}
+
+impl Transport for UnixStream {
+    type Address = PathBuf;
+    type Listener = UnixListener;
+
+    /// Replaces a socket file left by a previous run.
+    async fn bind(address: &Self::Address) -> eyre::Result<Self::Listener> {
+        match tokio::fs::remove_file(address).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).wrap_err("removing stale unix socket")
+            }
+            _ => {}
+        }
+        UnixListener::bind(address).wrap_err("bind")
+    }
+
+    async fn connect(address: &Self::Address) -> eyre::Result<Self> {
+        Self::connect(address).await.wrap_err("Could not connect")
+    }
+
+    async fn write(&mut self, buffer: Bytes) -> eyre::Result<()> {
+        self.write_all(buffer.as_bytes())
+            .await
+            .wrap_err("writing to socket")?;
+        Ok(())
+    }
+
+    async fn read(&mut self, buffer: &mut BytesMut) -> eyre::Result<usize> {
+        let size = self
+            .read_buf(buffer)
+            .await
+            .wrap_err("reading from socket")?;
+        Ok(size)
+    }
+}
+
+impl Listener for UnixListener {
+    type Transport = UnixStream;
+
+    async fn accept(
+        &mut self,
+    ) -> eyre::Result<(Self::Transport, <Self::Transport as Transport>::Address)> {
+        let (s, _) = UnixListener::accept(&*self)
+            .await
+            .wrap_err("accepting connection")?;
+        let path = self
+            .local_addr()
+            .ok()
+            .and_then(|it| it.as_pathname().map(|it| it.to_owned()))
+            .unwrap_or_default();
+
+        Ok((s, path))
     }
 }
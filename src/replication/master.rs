@@ -5,26 +5,36 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+use async_trait::async_trait;
 use bytes::Bytes;
 use tokio::{
-    net::TcpStream,
     select,
     sync::{mpsc, oneshot, Notify},
-    time::timeout,
+    time::{interval, timeout},
 };
 use tracing::instrument;
 
 use crate::{
+    clients::SharedClients,
+    config::{ClientClass, Config},
     engine::SharedEngine,
-    network::{Network, NetworkExt, NodeId, RedisNetwork},
-    replication::{OffsetId, ReplicationState, SharedTopology},
+    error::RedisError,
+    network::{Link, Network, NetworkExt, NodeId, RedisNetwork},
+    replication::{OffsetId, ReplicationState, SharedTopology, Topology},
+    request::{Extension, FromRequest, Request},
 };
 
-pub type ReplicaConnectionQueue = mpsc::Sender<(TcpStream, NodeId, OffsetId)>;
+/// How often replica connections are checked for being closed or timed out.
+pub(super) const HEALTH_CHECK_PERIOD: Duration = Duration::from_secs(1);
+/// Size of serialized `REPLCONF GETACK *`.
+const GETACK_SIZE: usize = 37;
+
+pub type ReplicaConnectionQueue = mpsc::Sender<(Link, NodeId, OffsetId)>;
 pub type ReplicationCommandQueue = mpsc::Sender<ReplicationCommand>;
 pub type ReplicationWaitQueue = mpsc::Sender<(usize, oneshot::Sender<usize>, Arc<Notify>)>;
 
 pub fn initiate(
+    config: Arc<Config>,
     engine: SharedEngine,
     topology: SharedTopology,
     state: ReplicationState,
@@ -34,6 +44,7 @@ pub fn initiate(
     let (txw, rxw) = mpsc::channel(1);
 
     tokio::spawn(replication_loop(
+        config,
         state,
         replications,
         topology,
@@ -45,6 +56,34 @@ pub fn initiate(
     Ok((tx, txw))
 }
 
+/// Extractor that rejects writes when there are not enough good replicas,
+/// as configured by `min-replicas-to-write` and `min-replicas-max-lag`.
+pub struct WriteGuard;
+
+#[async_trait]
+impl FromRequest for WriteGuard {
+    async fn from_request(request: Request) -> Result<Self, RedisError> {
+        // writes wait for `CLIENT PAUSE WRITE` to end before checking replicas
+        if let Ok(Extension(clients)) =
+            Extension::<SharedClients>::from_request(request.clone()).await
+        {
+            clients.paused(true).await;
+        }
+
+        let Extension(config) = Extension::<Arc<Config>>::from_request(request.clone()).await?;
+        if config.min_replicas_to_write == 0 {
+            return Ok(Self);
+        }
+
+        let Extension(topology) = Extension::<SharedTopology>::from_request(request).await?;
+        if topology.good_replicas(config.min_replicas_max_lag) < config.min_replicas_to_write {
+            return Err(RedisError::NoReplicas);
+        }
+
+        Ok(Self)
+    }
+}
+
 pub enum ReplicationCommand {
     Write {
         key: String,
@@ -54,26 +93,23 @@ pub enum ReplicationCommand {
 }
 
 async fn replication_loop(
+    config: Arc<Config>,
     state: ReplicationState,
     mut commands: mpsc::Receiver<ReplicationCommand>,
     topology: SharedTopology,
-    mut clients: mpsc::Receiver<(TcpStream, NodeId, OffsetId)>,
+    mut clients: mpsc::Receiver<(Link, NodeId, OffsetId)>,
     engine: SharedEngine,
     mut waits: mpsc::Receiver<(usize, oneshot::Sender<usize>, Arc<Notify>)>,
 ) -> eyre::Result<()> {
-    let mut network = RedisNetwork::new(None).await?;
+    let mut network = RedisNetwork::new(None, None).await?;
     let mut offsets = HashMap::new();
+    let mut health_check = interval(HEALTH_CHECK_PERIOD);
 
     loop {
         select! {
-            Some((connection, node, offset)) = clients.recv() => {
-                tracing::info!(?node, ?offset, "Adding new replication node");
-                network.add_connection(&node, connection)?;
-                topology.add(node).unwrap();
-                offsets.insert(node, offset);
-                let fullresync = format!("FULLRESYNC {} {}", state.id(), 0);
-                network.send(&node, &fullresync).await?;
-                network.send_rdb(&node, engine.dump()).await?;
+            Some((connection, node, _)) = clients.recv() => {
+                let replicas = pending_replicas(connection, node, &mut clients);
+                add_replicas(replicas, &config, &state, &topology, &engine, &mut network, &mut offsets).await?;
             },
             Some(command) = commands.recv() => {
                 tracing::trace!("Replication command received");
@@ -81,8 +117,12 @@ async fn replication_loop(
                 match command {
                     ReplicationCommand::Write{ key, value, expiration } => {
                         assert!(expiration.is_none(), "Can't propagate writes with eol yet");
-                        let size = network.broadcast(&vec![Bytes::from_static(b"SET"), Bytes::from(key), Bytes::from(value)]).await?;
+                        let (size, failed) = network.broadcast(&vec![Bytes::from_static(b"SET"), Bytes::from(key), Bytes::from(value)]).await?;
                         state.increment_offset(size as u64);
+                        for node in failed {
+                            tracing::warn!(?node, "Failed to propagate write to replica");
+                            remove_replica(&node, &topology, &mut network, &mut offsets);
+                        }
                     }
                 }
             },
@@ -90,6 +130,7 @@ async fn replication_loop(
                 let target_offset = state.offset();
                 collect_offsets(
                     state.clone(),
+                    &topology,
                     target_offset,
                     &mut offsets,
                     count,
@@ -98,12 +139,182 @@ async fn replication_loop(
                     not_intersted
                 ).await;
             }
+            failed = network.write_pending() => {
+                for node in failed {
+                    remove_replica(&node, &topology, &mut network, &mut offsets);
+                }
+            }
+            _ = health_check.tick() => {
+                for node in network.disconnected() {
+                    tracing::warn!(?node, "Replica closed the connection");
+                    remove_replica(&node, &topology, &mut network, &mut offsets);
+                }
+                for node in network.check_output_limits() {
+                    remove_replica(&node, &topology, &mut network, &mut offsets);
+                }
+                drain_acks(&topology, &mut network, &mut offsets);
+                for node in topology.timed_out(config.repl_timeout) {
+                    tracing::warn!(?node, timeout = ?config.repl_timeout, "Replica didn't acknowledge offset in time");
+                    remove_replica(&node, &topology, &mut network, &mut offsets);
+                }
+                // replicas only ack when asked, so ask every period to keep their lag fresh
+                // and notice the ones that stopped responding
+                if !offsets.is_empty() {
+                    request_acks(&state, &topology, &mut network, &mut offsets).await;
+                }
+            }
+        }
+    }
+}
+
+/// Collects replicas that are already waiting for synchronization,
+/// so they can share a single snapshot.
+pub(super) fn pending_replicas(
+    connection: Link,
+    node: NodeId,
+    clients: &mut mpsc::Receiver<(Link, NodeId, OffsetId)>,
+) -> Vec<(Link, NodeId)> {
+    let mut replicas = vec![(connection, node)];
+    while let Ok((connection, node, _)) = clients.try_recv() {
+        replicas.push((connection, node));
+    }
+    replicas
+}
+
+/// Registers replicas and sends them a full snapshot, so they continue
+/// replication from the current offset. With diskless sync a single snapshot
+/// is streamed to all of them while it's being generated.
+pub(super) async fn add_replicas(
+    replicas: Vec<(Link, NodeId)>,
+    config: &Config,
+    state: &ReplicationState,
+    topology: &SharedTopology,
+    engine: &SharedEngine,
+    network: &mut RedisNetwork,
+    offsets: &mut HashMap<NodeId, OffsetId>,
+) -> eyre::Result<()> {
+    let diskless = config.repl_diskless_sync;
+    let limit = config.client_output_buffer_limit.get(ClientClass::Replica);
+    let offset = state.offset();
+    let fullresync = format!("FULLRESYNC {} {}", state.id(), offset);
+    let mut synced = vec![];
+
+    for (connection, node) in replicas {
+        tracing::info!(?node, ?offset, diskless, "Adding new replication node");
+        network.add_connection(&node, connection, limit, config.repl_timeout)?;
+        // replicas are only part of the topology once they completed `PSYNC`
+        topology.add(node);
+        offsets.insert(node, offset);
+
+        let result = match network.send(&node, &fullresync).await {
+            Ok(_) if diskless => Ok(()),
+            Ok(_) => match engine.dump() {
+                Ok(snapshot) => network.send_rdb(&node, snapshot).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => synced.push(node),
+            Err(error) => {
+                tracing::warn!(?node, %error, "Failed to send snapshot to replica");
+                remove_replica(&node, topology, network, offsets);
+            }
+        }
+    }
+
+    if diskless && !synced.is_empty() {
+        for node in network
+            .send_rdb_diskless(&synced, engine.dump_stream())
+            .await
+        {
+            tracing::warn!(?node, "Failed to stream snapshot to replica");
+            remove_replica(&node, topology, network, offsets);
+        }
+    }
+    // replicas that failed were removed already
+    for node in synced {
+        topology.set_online(node, offset);
+    }
+
+    Ok(())
+}
+
+pub(super) fn remove_replica(
+    node: &NodeId,
+    topology: &SharedTopology,
+    network: &mut RedisNetwork,
+    offsets: &mut HashMap<NodeId, OffsetId>,
+) {
+    network.remove_connection(node);
+    offsets.remove(node);
+    if topology.remove(node) {
+        tracing::info!(
+            ?node,
+            connected = offsets.len(),
+            dropped_total = topology.dropped_replicas(),
+            "Replica removed from topology"
+        );
+    }
+}
+
+/// Asks every replica to acknowledge its offset, returning the size of the request.
+async fn request_acks(
+    state: &ReplicationState,
+    topology: &SharedTopology,
+    network: &mut RedisNetwork,
+    offsets: &mut HashMap<NodeId, OffsetId>,
+) -> Option<usize> {
+    let Ok((bytes_sent, failed)) = network
+        .broadcast(&[
+            Bytes::from_static(b"REPLCONF"),
+            Bytes::from_static(b"GETACK"),
+            Bytes::from_static(b"*"),
+        ])
+        .await
+    else {
+        return None;
+    };
+
+    state.increment_offset(bytes_sent as u64);
+
+    for node in failed {
+        tracing::warn!(?node, "Failed to request ack from replica");
+        remove_replica(&node, topology, network, offsets);
+    }
+
+    Some(bytes_sent)
+}
+
+/// Applies acks that replicas have already sent without waiting for new ones.
+pub(super) fn drain_acks(
+    topology: &SharedTopology,
+    network: &mut RedisNetwork,
+    offsets: &mut HashMap<NodeId, OffsetId>,
+) {
+    for (node, offset) in offsets.iter_mut() {
+        while let Some((response, _)) = network.try_receive::<Vec<String>>(node) {
+            apply_ack(node, offset, &response, topology);
         }
     }
 }
 
+/// Replica reports the offset it had before processing `GETACK`,
+/// so the request itself is added on top.
+fn apply_ack(node: &NodeId, offset: &mut OffsetId, response: &[String], topology: &Topology) {
+    let Some(Ok(ack)) = response.get(2).map(|it| it.parse::<u64>()) else {
+        return;
+    };
+    *offset = (ack + GETACK_SIZE as u64).into();
+    topology.ack(node, *offset);
+
+    tracing::info!(?node, %offset, "Received ack from replica");
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn collect_offsets(
     state: ReplicationState,
+    topology: &SharedTopology,
     target_offset: OffsetId,
     offsets: &mut HashMap<NodeId, OffsetId>,
     required_count: usize,
@@ -111,27 +322,27 @@ async fn collect_offsets(
     notify: oneshot::Sender<usize>,
     not_interested: Arc<Notify>,
 ) {
-    let required_count = min(required_count, offsets.len());
     let mut current_count = offsets
         .iter()
         .filter_map(|(_, &o)| if o == target_offset { Some(()) } else { None })
         .count();
 
-    if current_count >= required_count {
+    if current_count >= min(required_count, offsets.len()) {
         let _ = notify.send(current_count);
         return;
     }
 
     loop {
         select! {
-            Some(c) = ack_round(state.clone(), network, offsets, target_offset) => {
+            Some(c) = ack_round(state.clone(), topology, network, offsets, target_offset) => {
                 current_count = c;
             },
             _ = not_interested.notified() => {
                 break
             }
         }
-        if current_count >= required_count {
+        // replicas might have been dropped during the round
+        if current_count >= min(required_count, offsets.len()) {
             break;
         }
     }
@@ -139,42 +350,37 @@ async fn collect_offsets(
     let _ = notify.send(current_count);
 }
 
-#[instrument(skip(network), ret)]
+#[instrument(skip(topology, network), ret)]
 async fn ack_round(
     state: ReplicationState,
+    topology: &SharedTopology,
     network: &mut RedisNetwork,
     offsets: &mut HashMap<NodeId, OffsetId>,
     target: OffsetId,
 ) -> Option<usize> {
-    let Ok(bytes_sent) = network
-        .broadcast(&[
-            Bytes::from_static(b"REPLCONF"),
-            Bytes::from_static(b"GETACK"),
-            Bytes::from_static(b"*"),
-        ])
-        .await
-    else {
-        return None;
-    };
-
-    state.increment_offset(bytes_sent as u64);
+    request_acks(&state, topology, network, offsets).await?;
+    let mut failed = vec![];
 
     for (node, offset) in offsets.iter_mut() {
-        let Ok(Ok((response, _))) = timeout(
+        let response = match timeout(
             Duration::from_millis(100),
-            network.receive::<Vec<String>>(&node),
+            network.receive::<Vec<String>>(node),
         )
         .await
-        else {
-            continue;
-        };
-        let Some(ack) = response.get(2) else { continue };
-        let Ok(ack) = ack.parse::<u64>() else {
-            continue;
+        {
+            Ok(Ok((response, _))) => response,
+            Ok(Err(_)) => {
+                failed.push(*node);
+                continue;
+            }
+            Err(_) => continue,
         };
-        *offset = (ack + bytes_sent as u64).into();
+        apply_ack(node, offset, &response, topology);
+    }
 
-        tracing::info!(?node, %offset, "Received ack from replica");
+    for node in failed {
+        tracing::warn!(?node, "Lost connection to replica while collecting acks");
+        remove_replica(&node, topology, network, offsets);
     }
 
     Some(
@@ -184,3 +390,95 @@ async fn ack_round(
             .count(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use super::*;
+    use crate::{
+        config::{LogFormat, LogLevel, OutputBufferLimits, TlsAuthClients},
+        engine::RedisEngine,
+        latency::LatencyMonitor,
+        storage::Memory,
+    };
+
+    fn config(repl_timeout: Duration) -> Config {
+        Config {
+            port: 0,
+            bind: Vec::new(),
+            unixsocket: None,
+            unixsocketperm: None,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::No,
+            tls_replication: false,
+            timeout: Duration::ZERO,
+            tcp_keepalive: Duration::ZERO,
+            maxclients: 0,
+            dir: None,
+            dbfilename: None,
+            repl_timeout,
+            min_replicas_to_write: 0,
+            min_replicas_max_lag: Duration::ZERO,
+            repl_diskless_sync: false,
+            client_query_buffer_limit: 0,
+            proto_max_bulk_len: 0,
+            client_output_buffer_limit: OutputBufferLimits::default(),
+            shutdown_timeout: Duration::ZERO,
+            slowlog_log_slower_than: None,
+            slowlog_max_len: 0,
+            latency_monitor_threshold: Duration::ZERO,
+            metrics_port: 0,
+            loglevel: LogLevel::default(),
+            logfile: None,
+            log_format: LogFormat::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replicas_that_stop_reading_are_dropped_after_repl_timeout() {
+        let config = Arc::new(config(Duration::from_secs(1)));
+        let (queue, _) = mpsc::channel(1);
+        let latency = Arc::new(LatencyMonitor::new(&config));
+        let engine: SharedEngine =
+            Arc::new(RedisEngine::new(Memory::default(), queue, latency, None));
+        let topology = Topology::master();
+        let (commands, replications) = mpsc::channel(16);
+        let (replicas, clients) = mpsc::channel(1);
+        let (_waits, rxw) = mpsc::channel(1);
+        tokio::spawn(replication_loop(
+            config,
+            ReplicationState::master(),
+            replications,
+            topology.clone(),
+            clients,
+            engine,
+            rxw,
+        ));
+
+        // the replica end is never read, so only the full sync fits
+        let (_replica, link) = tokio::io::duplex(1024);
+        let node = NodeId::replica(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 6380));
+        replicas
+            .send((Box::new(link), node, OffsetId::default()))
+            .await
+            .unwrap();
+        for _ in 0..16 {
+            let write = ReplicationCommand::Write {
+                key: "key".to_string(),
+                value: "value".repeat(100),
+                expiration: None,
+            };
+            commands.send(write).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(topology.replicas().len(), 1);
+
+        tokio::time::sleep(HEALTH_CHECK_PERIOD * 2).await;
+        assert!(topology.replicas().is_empty());
+        assert_eq!(topology.dropped_replicas(), 1);
+    }
+}
@@ -1,19 +1,29 @@
 pub mod master;
 pub mod replica;
 
-use std::{fmt, ops::AddAssign, str::FromStr, sync::Arc};
+use std::{
+    fmt,
+    ops::AddAssign,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use derive_more::{Add, AddAssign, Display, From, Into};
 use eyre::WrapErr;
 use parking_lot::Mutex;
 
-use crate::{error::RedisError, network::NodeId};
+use crate::network::NodeId;
 
 pub type SharedTopology = Arc<Topology>;
 
 #[derive(Clone, Debug)]
 pub struct ReplicationState {
     offset: Arc<Mutex<OffsetId>>,
+    read_offset: Arc<Mutex<OffsetId>>,
     id: Arc<Mutex<ReplicationId>>,
     role: Arc<NodeRole>,
 }
@@ -22,6 +32,7 @@ impl ReplicationState {
     pub fn master() -> Self {
         Self {
             offset: Arc::new(Mutex::new(OffsetId::default())),
+            read_offset: Arc::new(Mutex::new(OffsetId::default())),
             id: Arc::new(Mutex::new(ReplicationId::random())),
             role: Arc::new(NodeRole::Master),
         }
@@ -30,6 +41,7 @@ impl ReplicationState {
     pub fn replica() -> Self {
         Self {
             offset: Arc::new(Mutex::new(OffsetId::default())),
+            read_offset: Arc::new(Mutex::new(OffsetId::default())),
             id: Arc::new(Mutex::new(ReplicationId::default())),
             role: Arc::new(NodeRole::Replica),
         }
@@ -38,13 +50,25 @@ impl ReplicationState {
         self.offset.lock().clone()
     }
     pub fn set_offset(&self, value: impl Into<OffsetId>) {
-        *self.offset.lock() = value.into();
+        let value = value.into();
+        *self.offset.lock() = value;
+        *self.read_offset.lock() = value;
     }
 
     pub fn increment_offset(&self, value: u64) {
         *self.offset.lock() += value;
     }
 
+    /// Offset of the replication stream received from master, including
+    /// commands that are not processed yet.
+    pub fn read_offset(&self) -> OffsetId {
+        *self.read_offset.lock()
+    }
+
+    pub fn increment_read_offset(&self, value: u64) {
+        *self.read_offset.lock() += value;
+    }
+
     pub fn id(&self) -> ReplicationId {
         self.id.lock().clone()
     }
@@ -57,7 +81,7 @@ impl ReplicationState {
     }
 }
 
-#[derive(Debug, Copy, Clone, Default, Into, From)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Into, From)]
 pub struct ReplicationId([u8; 20]);
 
 impl ReplicationId {
@@ -108,32 +132,175 @@ impl AddAssign<usize> for OffsetId {
     }
 }
 
+/// Replication neighbours of this node: the master it replicates from, if any,
+/// and replicas connected to it. Replicas can serve their own sub-replicas.
 #[derive(Debug)]
-pub enum Topology {
-    Master { replicas: Mutex<Vec<NodeId>> },
-    Replica { master: NodeId },
+pub struct Topology {
+    master: Option<(NodeId, Mutex<MasterLink>)>,
+    replicas: Mutex<Vec<ReplicaInfo>>,
+    dropped: AtomicU64,
 }
 
 impl Topology {
     pub fn master() -> SharedTopology {
-        Arc::new(Self::Master {
+        Arc::new(Self {
+            master: None,
             replicas: Mutex::new(vec![]),
+            dropped: AtomicU64::new(0),
         })
     }
 
     pub fn replica(master: NodeId) -> SharedTopology {
-        Arc::new(Self::Replica { master })
+        Arc::new(Self {
+            master: Some((master, Mutex::new(MasterLink::default()))),
+            replicas: Mutex::new(vec![]),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn add(&self, replica: NodeId) {
+        let mut replicas = self.replicas.lock();
+        match replicas.iter_mut().find(|it| it.node == replica) {
+            Some(existing) => *existing = ReplicaInfo::new(replica),
+            None => replicas.push(ReplicaInfo::new(replica)),
+        }
+    }
+
+    /// Marks a replica that received its snapshot as online, it's expected to ack from now on.
+    pub fn set_online(&self, replica: NodeId, offset: OffsetId) {
+        self.update(&replica, |info| {
+            info.state = ReplicaState::Online;
+            info.offset = offset;
+            info.last_ack = Instant::now();
+        });
+    }
+
+    /// Removes a replica, returning `true` if it was part of the topology.
+    pub fn remove(&self, replica: &NodeId) -> bool {
+        let mut replicas = self.replicas.lock();
+        let before = replicas.len();
+        replicas.retain(|it| it.node != *replica);
+        if replicas.len() == before {
+            return false;
+        }
+
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Total number of replicas removed because of disconnects or timeouts.
+    pub fn dropped_replicas(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn ack(&self, replica: &NodeId, offset: OffsetId) {
+        self.update(replica, |info| {
+            info.offset = offset;
+            info.last_ack = Instant::now();
+        });
+    }
+
+    /// Online replicas that didn't acknowledge their offset within `timeout`, acks are
+    /// requested periodically so this is the last interaction with them.
+    pub fn timed_out(&self, timeout: Duration) -> Vec<NodeId> {
+        self.replicas
+            .lock()
+            .iter()
+            .filter(|it| it.state == ReplicaState::Online && it.last_ack.elapsed() > timeout)
+            .map(|it| it.node)
+            .collect()
+    }
+
+    /// Number of online replicas that acknowledged their offset within `max_lag`.
+    pub fn good_replicas(&self, max_lag: Duration) -> usize {
+        self.replicas
+            .lock()
+            .iter()
+            .filter(|it| it.state == ReplicaState::Online && it.last_ack.elapsed() <= max_lag)
+            .count()
+    }
+
+    pub fn replicas(&self) -> Vec<ReplicaInfo> {
+        self.replicas.lock().clone()
+    }
+
+    pub fn master_link(&self) -> Option<(NodeId, MasterLink)> {
+        self.master
+            .as_ref()
+            .map(|(master, link)| (*master, *link.lock()))
+    }
+
+    pub fn set_link_up(&self, up: bool) {
+        if let Some((_, link)) = &self.master {
+            let mut link = link.lock();
+            link.up = up;
+            link.last_io = Instant::now();
+        }
+    }
+
+    pub fn touch_link(&self) {
+        if let Some((_, link)) = &self.master {
+            link.lock().last_io = Instant::now();
+        }
+    }
+
+    fn update(&self, replica: &NodeId, f: impl FnOnce(&mut ReplicaInfo)) {
+        if let Some(info) = self
+            .replicas
+            .lock()
+            .iter_mut()
+            .find(|it| it.node == *replica)
+        {
+            f(info);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplicaInfo {
+    pub node: NodeId,
+    pub state: ReplicaState,
+    pub offset: OffsetId,
+    pub last_ack: Instant,
+}
+
+impl ReplicaInfo {
+    fn new(node: NodeId) -> Self {
+        Self {
+            node,
+            state: ReplicaState::WaitBgsave,
+            offset: OffsetId::default(),
+            last_ack: Instant::now(),
+        }
+    }
+
+    /// Seconds passed since the replica acknowledged its offset, acks are requested every
+    /// second once it's online.
+    pub fn lag(&self) -> u64 {
+        self.last_ack.elapsed().as_secs()
     }
+}
 
-    pub fn add(&self, replica: NodeId) -> Result<(), RedisError> {
-        let Self::Master { replicas } = self else {
-            return Err(RedisError::NotMaster);
-        };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ReplicaState {
+    #[display(fmt = "wait_bgsave")]
+    WaitBgsave,
+    #[display(fmt = "online")]
+    Online,
+}
 
-        let mut replicas = replicas.lock();
-        replicas.push(replica);
+#[derive(Debug, Clone, Copy)]
+pub struct MasterLink {
+    pub up: bool,
+    pub last_io: Instant,
+}
 
-        Ok(())
+impl Default for MasterLink {
+    fn default() -> Self {
+        Self {
+            up: false,
+            last_io: Instant::now(),
+        }
     }
 }
 
@@ -144,3 +311,49 @@ pub enum NodeRole {
     #[display(fmt = "slave")]
     Replica,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(port: u16) -> NodeId {
+        NodeId::replica(([127, 0, 0, 1], port).into())
+    }
+
+    #[test]
+    fn replicas_time_out_after_their_last_ack() {
+        let topology = Topology::master();
+        for replica in [node(1), node(2)] {
+            topology.add(replica);
+            topology.set_online(replica, OffsetId::default());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        topology.ack(&node(2), OffsetId::from(37));
+
+        assert_eq!(topology.timed_out(Duration::from_millis(10)), [node(1)]);
+        assert!(topology.timed_out(Duration::from_secs(60)).is_empty());
+    }
+
+    #[test]
+    fn removed_replicas_are_counted_once() {
+        let topology = Topology::master();
+        topology.add(node(1));
+
+        assert!(topology.remove(&node(1)));
+        assert!(!topology.remove(&node(1)));
+        assert_eq!(topology.dropped_replicas(), 1);
+    }
+
+    #[test]
+    fn replicas_are_online_once_synced() {
+        let topology = Topology::master();
+        topology.set_online(node(1), OffsetId::default());
+        assert!(topology.replicas().is_empty());
+
+        topology.add(node(1));
+        assert_eq!(topology.replicas()[0].state, ReplicaState::WaitBgsave);
+        topology.set_online(node(1), OffsetId::from(10));
+        assert_eq!(topology.replicas()[0].state, ReplicaState::Online);
+        assert_eq!(topology.replicas()[0].offset, OffsetId::from(10));
+    }
+}
@@ -1,48 +1,109 @@
+use std::{collections::HashMap, sync::Arc};
+
 use bytes::Bytes;
-use tokio::{select, sync::mpsc};
+use tokio::{select, sync::mpsc, time::interval};
 use tower::ServiceExt;
 use tracing::instrument;
 
 use crate::{
+    args,
+    commands::table,
+    config::Config,
     engine::SharedEngine,
-    flag,
-    network::{Network, NetworkExt, NodeId, RedisNetwork},
-    replication::{master::ReplicationCommand, ReplicationState},
+    error::RedisError,
+    network::{Link, Network, NetworkExt, NodeId, RedisNetwork},
+    replication::{
+        master::{
+            add_replicas, drain_acks, pending_replicas, remove_replica, ReplicationCommand,
+            HEALTH_CHECK_PERIOD,
+        },
+        OffsetId, ReplicationState, SharedTopology,
+    },
     request::{Arg, Extension, Request},
-    response::{IntoResponse, Resp2},
+    response::{IntoResponse, Resp},
     routing::Router,
     state::ConnectionState,
 };
 
-#[instrument(skip(network, engine, acks), err)]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(config, network, engine, topology, acks, clients), err)]
 pub async fn start(
+    config: Arc<Config>,
     mut network: RedisNetwork,
     master: NodeId,
     engine: SharedEngine,
     state: ReplicationState,
+    topology: SharedTopology,
     mut acks: mpsc::Receiver<ReplicationCommand>,
+    mut clients: mpsc::Receiver<(Link, NodeId, OffsetId)>,
 ) -> eyre::Result<()> {
     let _ = network.receive_rdb(&master).await?;
     tracing::info!("Received serialized rdb state");
+    topology.set_link_up(true);
 
     let router = Router::new()
-        .route("set", set)
-        .route("replconf", replconf)
-        .route("ping", ping)
+        .route(table::SET, set)
+        .route(table::REPLCONF, replconf)
+        .route(table::PING, ping)
         .layer(Extension(state.clone()))
         .layer(Extension(engine.clone()));
 
-    let connection = ConnectionState::new(master.addr());
+    let connection = ConnectionState::new(master.addr().into());
+    // sub-replicas receive exactly the same stream, so their offsets match ours
+    let mut downstream = RedisNetwork::new(None, None).await?;
+    let mut offsets = HashMap::new();
+    let mut health_check = interval(HEALTH_CHECK_PERIOD);
 
     loop {
         select! {
-            Ok((request, count)) = network.receive::<Vec<String>>(&master) => {
-                tracing::debug!(?request, "Received command from master");
+            received = network.receive_frame::<Vec<String>>(&master) => {
+                let Ok((request, frame)) = received else {
+                    tracing::warn!("Lost connection to master");
+                    topology.set_link_up(false);
+                    return Ok(());
+                };
+                let count = frame.len();
+                topology.touch_link();
+                state.increment_read_offset(count as u64);
 
                 let request = Request::from_command_line(request, connection.clone())?;
+                tracing::debug!(command = %request.command, size = count, "Received command from master");
                 let response = router.clone().oneshot(request).await.into_response();
                 network.respond(&master, response).await?;
                 state.increment_offset(count as u64);
+
+                if !offsets.is_empty() {
+                    let (_, failed) = downstream.broadcast_raw(frame).await?;
+                    for node in failed {
+                        tracing::warn!(?node, "Failed to forward replication stream to sub-replica");
+                        remove_replica(&node, &topology, &mut downstream, &mut offsets);
+                    }
+                }
+            }
+            Some((connection, node, _)) = clients.recv() => {
+                let replicas = pending_replicas(connection, node, &mut clients);
+                add_replicas(replicas, &config, &state, &topology, &engine, &mut downstream, &mut offsets).await?;
+            }
+            failed = downstream.write_pending() => {
+                for node in failed {
+                    remove_replica(&node, &topology, &mut downstream, &mut offsets);
+                }
+            }
+            _ = health_check.tick() => {
+                for node in downstream.disconnected() {
+                    tracing::warn!(?node, "Sub-replica closed the connection");
+                    remove_replica(&node, &topology, &mut downstream, &mut offsets);
+                }
+                for node in downstream.check_output_limits() {
+                    remove_replica(&node, &topology, &mut downstream, &mut offsets);
+                }
+                // sub-replicas answer the `GETACK`s forwarded from our master, asking them
+                // ourselves would make their offsets diverge from the stream
+                drain_acks(&topology, &mut downstream, &mut offsets);
+                for node in topology.timed_out(config.repl_timeout) {
+                    tracing::warn!(?node, timeout = ?config.repl_timeout, "Sub-replica didn't acknowledge offset in time");
+                    remove_replica(&node, &topology, &mut downstream, &mut offsets);
+                }
             }
             Some(_) = acks.recv() => {
 
@@ -57,17 +118,26 @@ async fn set(Extension(storage): Extension<SharedEngine>, Arg(key): Arg<1>, Arg(
     let _ = storage.set(&key, value, None).await;
 }
 
-flag!(GetAck, "GETACK");
+args! {
+    /// `REPLCONF GETACK *`, the only one sent by masters to replicas.
+    struct ReplconfArgs {
+        #[option("GETACK")]
+        getack: Option<String>,
+    }
+}
 
 async fn replconf(
     Extension(state): Extension<ReplicationState>,
-    GetAck(_k): GetAck,
-) -> impl IntoResponse {
+    args: ReplconfArgs,
+) -> Result<impl IntoResponse, RedisError> {
+    if args.getack.is_none() {
+        return Err(RedisError::Syntax);
+    }
     let value = state.offset();
 
-    Resp2(vec![
+    Ok(Resp(vec![
         Bytes::from_static(b"REPLCONF"),
         Bytes::from_static(b"ACK"),
         Bytes::from(value.to_string()),
-    ])
+    ]))
 }
@@ -0,0 +1,422 @@
+use std::str::FromStr;
+
+use crate::{error::RedisError, request::Request};
+
+/// Cursor over the arguments of a request, used by the extractors generated with [`args!`].
+///
+/// Positionals are read first, then options in any order until an argument isn't a known
+/// keyword, so a value that looks like a keyword is never misread as one.
+pub struct ArgParser {
+    command: String,
+    args: std::vec::IntoIter<String>,
+}
+
+impl ArgParser {
+    pub fn new(request: Request) -> Self {
+        Self {
+            command: request.command,
+            args: request.args.into_iter(),
+        }
+    }
+
+    pub fn peek(&self) -> Option<&str> {
+        self.args.as_slice().first().map(|it| it.as_str())
+    }
+
+    /// Next positional argument, its absence is an arity error.
+    pub fn next<T>(&mut self) -> Result<T, RedisError>
+    where
+        T: FromStr,
+        RedisError: From<T::Err>,
+    {
+        match self.args.next() {
+            Some(value) => Ok(value.parse()?),
+            None => Err(RedisError::WrongArity(self.command.clone())),
+        }
+    }
+
+    pub fn optional<T>(&mut self) -> Result<Option<T>, RedisError>
+    where
+        T: FromStr,
+        RedisError: From<T::Err>,
+    {
+        match self.args.next() {
+            Some(value) => Ok(Some(value.parse()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Consumes the next argument if it's `keyword`, in any case.
+    pub fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self
+            .peek()
+            .is_some_and(|it| it.eq_ignore_ascii_case(keyword));
+        if found {
+            self.args.next();
+        }
+        found
+    }
+
+    /// `KEYWORD`, given at most once.
+    pub fn flag(&mut self, slot: &mut bool, keyword: &str) -> Result<bool, RedisError> {
+        if !self.keyword(keyword) {
+            return Ok(false);
+        }
+        if std::mem::replace(slot, true) {
+            return Err(RedisError::Syntax);
+        }
+        Ok(true)
+    }
+
+    /// `KEYWORD value`, given at most once.
+    pub fn option<T>(&mut self, slot: &mut Option<T>, keyword: &str) -> Result<bool, RedisError>
+    where
+        T: FromStr,
+        RedisError: From<T::Err>,
+    {
+        if !self.keyword(keyword) {
+            return Ok(false);
+        }
+        let value = self.args.next().ok_or(RedisError::Syntax)?;
+        if slot.replace(value.parse()?).is_some() {
+            return Err(RedisError::Syntax);
+        }
+        Ok(true)
+    }
+
+    /// One of mutually exclusive options, given at most once.
+    pub fn one_of<T: OneOf>(&mut self, slot: &mut Option<T>) -> Result<bool, RedisError> {
+        let Some(keyword) = self.peek().map(|it| it.to_uppercase()) else {
+            return Ok(false);
+        };
+        if !T::KEYWORDS.contains(&keyword.as_str()) {
+            return Ok(false);
+        }
+        self.args.next();
+        if slot.is_some() {
+            return Err(RedisError::Syntax);
+        }
+        *slot = Some(T::parse(&keyword, self)?);
+        Ok(true)
+    }
+
+    /// All the remaining arguments.
+    pub fn rest<T>(&mut self) -> Result<Vec<T>, RedisError>
+    where
+        T: FromStr,
+        RedisError: From<T::Err>,
+    {
+        self.args.by_ref().map(|it| Ok(it.parse()?)).collect()
+    }
+
+    /// Remaining arguments as `key value` pairs, like the fields of `XADD`.
+    pub fn pairs<K, V>(&mut self) -> Result<Vec<(K, V)>, RedisError>
+    where
+        K: FromStr,
+        V: FromStr,
+        RedisError: From<K::Err> + From<V::Err>,
+    {
+        if self.args.len() % 2 == 1 {
+            return Err(RedisError::WrongArity(self.command.clone()));
+        }
+        let mut pairs = Vec::with_capacity(self.args.len() / 2);
+        while let (Some(key), Some(value)) = (self.args.next(), self.args.next()) {
+            pairs.push((key.parse()?, value.parse()?));
+        }
+        Ok(pairs)
+    }
+
+    /// Arguments after `keyword` split in halves and zipped, like `STREAMS key [key ...] id [id ...]`.
+    pub fn split<K, V>(&mut self, keyword: &str) -> Result<Vec<(K, V)>, RedisError>
+    where
+        K: FromStr,
+        V: FromStr,
+        RedisError: From<K::Err> + From<V::Err>,
+    {
+        if !self.keyword(keyword) {
+            return Err(RedisError::Syntax);
+        }
+        let rest = self.args.by_ref().collect::<Vec<_>>();
+        if rest.is_empty() || rest.len() % 2 == 1 {
+            return Err(RedisError::UnbalancedStreams(self.command.clone()));
+        }
+        let (keys, values) = rest.split_at(rest.len() / 2);
+        keys.iter()
+            .zip(values)
+            .map(|(key, value)| Ok((key.parse()?, value.parse()?)))
+            .collect()
+    }
+
+    /// Fails on leftover arguments.
+    pub fn finish(self) -> Result<(), RedisError> {
+        match self.args.len() {
+            0 => Ok(()),
+            _ => Err(RedisError::Syntax),
+        }
+    }
+}
+
+/// Mutually exclusive options, e.g. the expiration of `SET`.
+pub trait OneOf: Sized {
+    /// Uppercase keywords of the variants.
+    const KEYWORDS: &'static [&'static str];
+
+    /// Parses the variant of `keyword`, already consumed from `parser`.
+    fn parse(keyword: &str, parser: &mut ArgParser) -> Result<Self, RedisError>;
+}
+
+/// Declares a struct of arguments that is extracted from requests.
+///
+/// Fields are parsed in this order, whatever their declaration order:
+/// - `name: T` required positionals, then `#[optional] name: Option<T>`;
+/// - options in any order, `#[flag("NX")] name: bool`, `#[option("PX")] name: Option<T>`
+///   and `#[one_of] name: Option<T>` for a [`OneOf`] type;
+/// - a tail, `#[rest] name: Vec<T>`, `#[pairs] name: Vec<(K, V)>` or
+///   `#[split("STREAMS")] name: Vec<(K, V)>`.
+///
+/// Leftover arguments are a syntax error.
+#[macro_export]
+macro_rules! args {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $( $(#[$kind:ident $(($keyword:literal))?])? $field:ident : $ty:ty ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $( pub $field: $ty, )*
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::request::FromRequest for $name {
+            async fn from_request(
+                request: $crate::request::Request,
+            ) -> Result<Self, $crate::error::RedisError> {
+                #[allow(unused_mut)]
+                let mut parser = $crate::request::ArgParser::new(request);
+
+                $( $crate::args!(@positional parser [$($kind)?] $field: $ty); )*
+                $( $crate::args!(@optional parser [$($kind)?] $field: $ty); )*
+                $( $crate::args!(@init [$($kind)?] $field: $ty); )*
+                #[allow(clippy::never_loop)]
+                loop {
+                    $( if $crate::args!(@option parser [$($kind $($keyword)?)?] $field) { continue; } )*
+                    break;
+                }
+                $( $crate::args!(@tail parser [$($kind $($keyword)?)?] $field: $ty); )*
+                parser.finish()?;
+
+                Ok(Self { $($field,)* })
+            }
+        }
+    };
+
+    (@positional $parser:ident [] $field:ident: $ty:ty) => {
+        let $field: $ty = $parser.next()?;
+    };
+    (@positional $parser:ident [$kind:ident] $field:ident: $ty:ty) => {};
+
+    (@optional $parser:ident [optional] $field:ident: $ty:ty) => {
+        let $field: $ty = $parser.optional()?;
+    };
+    (@optional $parser:ident [$($kind:ident)?] $field:ident: $ty:ty) => {};
+
+    (@init [flag] $field:ident: $ty:ty) => {
+        let mut $field: $ty = false;
+    };
+    (@init [option] $field:ident: $ty:ty) => {
+        let mut $field: $ty = None;
+    };
+    (@init [one_of] $field:ident: $ty:ty) => {
+        let mut $field: $ty = None;
+    };
+    (@init [$($kind:ident)?] $field:ident: $ty:ty) => {};
+
+    (@option $parser:ident [flag $keyword:literal] $field:ident) => {
+        $parser.flag(&mut $field, $keyword)?
+    };
+    (@option $parser:ident [option $keyword:literal] $field:ident) => {
+        $parser.option(&mut $field, $keyword)?
+    };
+    (@option $parser:ident [one_of] $field:ident) => {
+        $parser.one_of(&mut $field)?
+    };
+    (@option $parser:ident [$($kind:ident $($keyword:literal)?)?] $field:ident) => {
+        false
+    };
+
+    (@tail $parser:ident [rest] $field:ident: $ty:ty) => {
+        let $field: $ty = $parser.rest()?;
+    };
+    (@tail $parser:ident [pairs] $field:ident: $ty:ty) => {
+        let $field: $ty = $parser.pairs()?;
+    };
+    (@tail $parser:ident [split $keyword:literal] $field:ident: $ty:ty) => {
+        let $field: $ty = $parser.split($keyword)?;
+    };
+    (@tail $parser:ident [$($kind:ident $($keyword:literal)?)?] $field:ident: $ty:ty) => {};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::{
+        error::RedisError,
+        request::{ArgParser, FromRequest, OneOf, Request},
+        state::ConnectionState,
+    };
+
+    #[derive(Debug, PartialEq)]
+    enum Expiry {
+        Ex(u64),
+        KeepTtl,
+    }
+
+    impl OneOf for Expiry {
+        const KEYWORDS: &'static [&'static str] = &["EX", "KEEPTTL"];
+
+        fn parse(keyword: &str, parser: &mut ArgParser) -> Result<Self, RedisError> {
+            match keyword {
+                "EX" => Ok(Self::Ex(parser.next()?)),
+                _ => Ok(Self::KeepTtl),
+            }
+        }
+    }
+
+    crate::args! {
+        struct SetArgs {
+            key: String,
+            value: String,
+            #[flag("NX")]
+            nx: bool,
+            #[option("GET")]
+            get: Option<String>,
+            #[one_of]
+            expiry: Option<Expiry>,
+        }
+    }
+
+    crate::args! {
+        struct AddArgs {
+            key: String,
+            #[optional]
+            id: Option<u64>,
+            #[pairs]
+            fields: Vec<(String, String)>,
+        }
+    }
+
+    crate::args! {
+        struct ReadArgs {
+            #[option("COUNT")]
+            count: Option<usize>,
+            #[split("STREAMS")]
+            streams: Vec<(String, String)>,
+        }
+    }
+
+    crate::args! {
+        struct DelArgs {
+            #[rest]
+            keys: Vec<String>,
+        }
+    }
+
+    async fn parse<T: FromRequest>(argv: &str) -> Result<T, RedisError> {
+        let state = ConnectionState::new(SocketAddr::from(([127, 0, 0, 1], 60866)).into());
+        let argv = argv.split(' ').map(|it| it.to_owned()).collect();
+        T::from_request(Request::from_command_line(argv, state).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn keywords_in_value_positions_are_values() {
+        let args = parse::<SetArgs>("SET NX NX").await.unwrap();
+        assert_eq!((args.key.as_str(), args.value.as_str()), ("NX", "NX"));
+        assert!(!args.nx);
+
+        let args = parse::<SetArgs>("SET key value nx GET ex EX 10")
+            .await
+            .unwrap();
+        assert!(args.nx);
+        assert_eq!(args.get.as_deref(), Some("ex"));
+        assert_eq!(args.expiry, Some(Expiry::Ex(10)));
+    }
+
+    #[tokio::test]
+    async fn options_are_given_at_most_once() {
+        for argv in [
+            "SET key value NX NX",
+            "SET key value GET a GET b",
+            "SET key value EX 10 KEEPTTL",
+        ] {
+            let result = parse::<SetArgs>(argv).await;
+            assert!(matches!(result, Err(RedisError::Syntax)), "{argv}");
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_and_invalid_values_fail() {
+        assert!(matches!(
+            parse::<SetArgs>("SET key").await,
+            Err(RedisError::WrongArity(command)) if command == "set"
+        ));
+        assert!(matches!(
+            parse::<SetArgs>("SET key value GET").await,
+            Err(RedisError::Syntax)
+        ));
+        assert!(matches!(
+            parse::<SetArgs>("SET key value EX soon").await,
+            Err(RedisError::ExpectedNumber(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn leftover_arguments_are_a_syntax_error() {
+        assert!(matches!(
+            parse::<SetArgs>("SET key value NX extra").await,
+            Err(RedisError::Syntax)
+        ));
+        assert_eq!(parse::<DelArgs>("DEL a b").await.unwrap().keys, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn pairs_must_be_complete() {
+        let args = parse::<AddArgs>("ADD key 1 a 1 b 2").await.unwrap();
+        assert_eq!((args.key.as_str(), args.id), ("key", Some(1)));
+        assert_eq!(
+            args.fields,
+            [("a".into(), "1".into()), ("b".into(), "2".into())]
+        );
+
+        assert!(matches!(
+            parse::<AddArgs>("ADD key 1 a 1 b").await,
+            Err(RedisError::WrongArity(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn split_halves_must_be_balanced() {
+        let args = parse::<ReadArgs>("READ COUNT 2 STREAMS a b 0 1")
+            .await
+            .unwrap();
+        assert_eq!(args.count, Some(2));
+        assert_eq!(
+            args.streams,
+            [("a".into(), "0".into()), ("b".into(), "1".into())]
+        );
+
+        for argv in ["READ STREAMS a b 0", "READ STREAMS"] {
+            let result = parse::<ReadArgs>(argv).await;
+            assert!(
+                matches!(result, Err(RedisError::UnbalancedStreams(_))),
+                "{argv}"
+            );
+        }
+        assert!(matches!(
+            parse::<ReadArgs>("READ COUNT 2 a 0").await,
+            Err(RedisError::Syntax)
+        ));
+    }
+}
@@ -1,17 +1,22 @@
+mod args;
 mod extension;
-mod flag;
 
 use std::str::FromStr;
 
 use async_trait::async_trait;
 
-pub use self::extension::Extension;
+pub use self::{
+    args::{ArgParser, OneOf},
+    extension::Extension,
+};
 use crate::{error::RedisError, state::ConnectionState, util::Extensions};
 
 #[derive(Debug, Clone)]
 pub struct Request {
     pub command: String,
     pub args: Vec<String>,
+    /// Name of the command as it was sent, `command` is lowercased for routing.
+    sent_command: String,
     state: ConnectionState,
     extensions: Extensions,
 }
@@ -22,11 +27,14 @@ impl Request {
         state: ConnectionState,
     ) -> Result<Self, RedisError> {
         let mut args = args.into_iter();
-        let command = args.next().ok_or_else(|| RedisError::Smth)?.to_lowercase();
+        let sent_command = args
+            .next()
+            .ok_or_else(|| RedisError::Protocol("empty request".to_owned()))?;
 
         Ok(Self {
-            command,
+            command: sent_command.to_lowercase(),
             args: args.collect(),
+            sent_command,
             state,
             extensions: Default::default(),
         })
@@ -40,9 +48,42 @@ impl Request {
         &mut self.extensions
     }
 
+    pub fn sent_command(&self) -> &str {
+        &self.sent_command
+    }
+
     pub fn state(&self) -> &ConnectionState {
         &self.state
     }
+
+    /// Command name and arguments as they were sent, with the credentials of `AUTH` and
+    /// `HELLO ... AUTH` replaced, so they can be shown by `MONITOR` and the slow log.
+    pub fn redacted_argv(&self) -> Vec<&str> {
+        const REDACTED: &str = "(redacted)";
+
+        let mut argv = self
+            .command
+            .split('|')
+            .chain(self.args.iter().map(|it| it.as_str()))
+            .collect::<Vec<_>>();
+        match self.command.as_str() {
+            "auth" => argv[1..].fill(REDACTED),
+            "hello" => {
+                // `AUTH username password`, the name of the command comes first in `argv`
+                if let Some(auth) = self
+                    .args
+                    .iter()
+                    .position(|it| it.eq_ignore_ascii_case("auth"))
+                {
+                    for arg in argv.iter_mut().skip(auth + 2).take(2) {
+                        *arg = REDACTED;
+                    }
+                }
+            }
+            _ => {}
+        }
+        argv
+    }
 }
 
 #[async_trait]
@@ -74,7 +115,7 @@ impl<const N: usize> FromRequest for Arg<N> {
         }
 
         match request.args.get(N - 1) {
-            None => Err(RedisError::Smth),
+            None => Err(RedisError::WrongArity(request.command.clone())),
             Some(v) => Ok(Arg(v.clone())),
         }
     }
@@ -1,15 +1,35 @@
-use bytes::Bytes;
-use eyre::WrapErr;
-use nom::AsBytes;
+use std::time::Instant;
+
+use bytes::{Buf, Bytes, BytesMut};
+use eyre::{bail, eyre, WrapErr};
+use futures_util::FutureExt;
 use serde::Serialize;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
 
-use crate::{encoding::resp2, error::RedisError, replication::OffsetId};
+use crate::{
+    config::OutputBufferLimit,
+    encoding::{
+        resp3::{self, Value},
+        Protocol,
+    },
+    error::RedisError,
+    monitor::MonitorFeed,
+    replication::OffsetId,
+};
 
 pub enum Response {
     Raw(Bytes),
+    /// Reply that is encoded with the protocol negotiated by the connection.
+    Value(Value),
     Empty,
-    Upgrade { offset: OffsetId },
+    Upgrade {
+        offset: OffsetId,
+    },
+    /// The connection only receives the lines of the feed from now on.
+    Monitor(MonitorFeed),
 }
 
 impl Response {
@@ -21,18 +41,192 @@ impl Response {
         matches!(self, Self::Upgrade { .. })
     }
 
-    pub async fn write(self, write: &mut (impl AsyncWrite + Unpin)) -> eyre::Result<()> {
+    /// Code of an error reply, like `ERR` or `WRONGTYPE`.
+    pub fn error_code(&self) -> Option<String> {
+        let message = match self {
+            Self::Raw(b) => b.strip_prefix(b"-")?,
+            Self::Value(Value::Error(e)) => e.as_bytes(),
+            _ => return None,
+        };
+        let code = message
+            .split(|it| it.is_ascii_whitespace())
+            .next()
+            .unwrap_or_default();
+        Some(String::from_utf8_lossy(code).into_owned())
+    }
+
+    pub fn value(v: impl Serialize) -> Self {
+        Self::Value(resp3::to_value(&v).expect("shouldn't really fail"))
+    }
+
+    /// Appends the encoded response to the output, returning its size.
+    pub fn write_to(self, output: &mut BytesMut, protocol: Protocol) -> usize {
+        let before = output.len();
         match self {
-            Self::Raw(b) => {
-                write
-                    .write_all(b.as_bytes())
-                    .await
-                    .wrap_err("Failed to write response")?;
-                Ok(())
+            Self::Raw(b) => output.extend_from_slice(&b),
+            Self::Value(v) => v.write(output, protocol),
+            Self::Empty => {}
+            // connections are handed off by `serve` before replies are written, so these are
+            // only seen here if a command returns them where no hand-off happens
+            Response::Upgrade { .. } | Response::Monitor(_) => {
+                tracing::error!("Connection hand-off returned as a reply");
+                RedisError::Unhandled(eyre!("this command can't be used here"))
+                    .into_response()
+                    .write_to(output, protocol);
             }
-            Self::Empty => Ok(()),
-            Response::Upgrade { .. } => unreachable!(),
         }
+        output.len() - before
+    }
+
+    pub async fn write(
+        self,
+        write: &mut (impl AsyncWrite + Unpin),
+        protocol: Protocol,
+    ) -> eyre::Result<()> {
+        let mut output = BytesMut::new();
+        self.write_to(&mut output, protocol);
+        write
+            .write_all(&output)
+            .await
+            .wrap_err("Failed to write response")?;
+        Ok(())
+    }
+}
+
+/// Replies to pipelined requests, written to the client at once.
+pub struct OutputBuffer {
+    data: BytesMut,
+    limit: OutputBufferLimit,
+    /// When the buffer went above the soft limit, reset once it's below again.
+    above_soft: Option<Instant>,
+    /// Data was written since the last flush, e.g. TLS might still hold some of it.
+    unflushed: bool,
+}
+
+impl OutputBuffer {
+    pub fn new(limit: OutputBufferLimit) -> Self {
+        Self {
+            data: BytesMut::new(),
+            limit,
+            above_soft: None,
+            unflushed: false,
+        }
+    }
+
+    /// Number of bytes waiting to be written.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Limit of the class the client belongs to now, e.g. once it registered as a replica.
+    pub fn set_limit(&mut self, limit: OutputBufferLimit) {
+        self.limit = limit;
+    }
+
+    /// Buffers the response, failing once the hard limit is reached.
+    pub fn push(&mut self, response: Response, protocol: Protocol) -> eyre::Result<()> {
+        response.write_to(&mut self.data, protocol);
+        self.check_hard_limit()
+    }
+
+    /// Buffers data that is already encoded, like the write stream sent to replicas.
+    pub fn push_raw(&mut self, data: &[u8]) -> eyre::Result<()> {
+        self.data.extend_from_slice(data);
+        self.check_hard_limit()
+    }
+
+    fn check_hard_limit(&self) -> eyre::Result<()> {
+        if self.limit.hard > 0 && self.data.len() >= self.limit.hard {
+            bail!(
+                "Output buffer of {} bytes reached hard limit",
+                self.data.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether some data still has to be written or flushed.
+    pub fn is_pending(&self) -> bool {
+        !self.data.is_empty() || self.unflushed
+    }
+
+    /// Fails once the buffer stayed above the soft limit for longer than allowed,
+    /// checked like redis does whenever the buffer changes or periodically.
+    pub fn check_soft_limit(&mut self) -> eyre::Result<()> {
+        if self.limit.soft == 0 || self.data.len() <= self.limit.soft {
+            self.above_soft = None;
+            return Ok(());
+        }
+        let since = *self.above_soft.get_or_insert_with(Instant::now);
+        if since.elapsed() > self.limit.soft_seconds {
+            bail!(
+                "Output buffer of {} bytes stayed above soft limit for {:?}",
+                self.data.len(),
+                self.limit.soft_seconds
+            );
+        }
+        Ok(())
+    }
+
+    /// Waits until part of the buffer is written, or flushed when everything was written.
+    /// Cancelling it doesn't lose any data.
+    pub async fn write_some(&mut self, write: &mut (impl AsyncWrite + Unpin)) -> eyre::Result<()> {
+        if self.data.is_empty() {
+            if self.unflushed {
+                write.flush().await.wrap_err("Failed to flush output")?;
+                self.unflushed = false;
+            }
+            return Ok(());
+        }
+
+        let written = write
+            .write(&self.data)
+            .await
+            .wrap_err("Failed to write output")?;
+        if written == 0 {
+            bail!("Connection closed with {} bytes pending", self.data.len());
+        }
+        self.data.advance(written);
+        self.unflushed = true;
+        self.check_soft_limit()
+    }
+
+    /// Writes as much as the connection accepts without blocking, the rest stays buffered.
+    pub fn write_available(&mut self, write: &mut (impl AsyncWrite + Unpin)) -> eyre::Result<()> {
+        while self.is_pending() {
+            match self.write_some(write).now_or_never() {
+                Some(result) => result?,
+                None => break,
+            }
+        }
+        self.check_soft_limit()
+    }
+
+    /// Writes buffered responses, failing if the client doesn't read them in time
+    /// while the buffer is above the soft limit.
+    pub async fn flush(&mut self, write: &mut (impl AsyncWrite + Unpin)) -> eyre::Result<()> {
+        if self.data.is_empty() {
+            return Ok(());
+        }
+
+        let data = self.data.split().freeze();
+        let write = write.write_all(&data);
+
+        if self.limit.soft > 0 && data.len() > self.limit.soft {
+            timeout(self.limit.soft_seconds, write)
+                .await
+                .map_err(|_| {
+                    eyre!(
+                        "Output buffer of {} bytes stayed above soft limit for {:?}",
+                        data.len(),
+                        self.limit.soft_seconds
+                    )
+                })?
+                .wrap_err("Failed to write response")?;
+        } else {
+            write.await.wrap_err("Failed to write response")?;
+        }
+        Ok(())
     }
 }
 
@@ -48,7 +242,7 @@ impl IntoResponse for Response {
 
 impl IntoResponse for Bytes {
     fn into_response(self) -> Response {
-        Response::Raw(resp2::to_bytes(&self).expect("shouldn't really fail"))
+        Response::value(self)
     }
 }
 
@@ -56,7 +250,7 @@ impl<T: IntoResponse> IntoResponse for Option<T> {
     fn into_response(self) -> Response {
         match self {
             Some(v) => v.into_response(),
-            None => Response::Raw(resp2::to_bytes(&Option::<()>::None).unwrap()),
+            None => Response::Value(Value::Null),
         }
     }
 }
@@ -65,7 +259,7 @@ macro_rules! impl_for_primitive {
     ($t: ty) => {
         impl IntoResponse for $t {
             fn into_response(self) -> Response {
-                Response::Raw(resp2::to_bytes(&self).expect("shouldn't really fail"))
+                Response::value(self)
             }
         }
     };
@@ -106,11 +300,11 @@ impl IntoResponse for () {
 
 #[derive(Debug, Serialize)]
 #[serde(transparent)]
-pub struct Resp2<T>(pub T);
+pub struct Resp<T>(pub T);
 
-impl<T: Serialize> IntoResponse for Resp2<T> {
+impl<T: Serialize> IntoResponse for Resp<T> {
     fn into_response(self) -> Response {
-        Response::Raw(resp2::to_bytes(&self).expect("shouldn't really fail"))
+        Response::value(self)
     }
 }
 
@@ -122,3 +316,70 @@ impl<L: IntoResponse, R: IntoResponse> IntoResponse for Result<L, R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn limited(hard: usize, soft: usize) -> OutputBuffer {
+        OutputBuffer::new(OutputBufferLimit {
+            hard,
+            soft,
+            soft_seconds: Duration::from_millis(50),
+        })
+    }
+
+    #[test]
+    fn hard_limit_fails_the_push() {
+        let mut output = limited(8, 0);
+        output.push_raw(b"1234567").unwrap();
+        assert!(output.push_raw(b"8").is_err());
+
+        output.set_limit(OutputBufferLimit::default());
+        output.push_raw(&[0; 1024]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn soft_limit_fails_flushes_that_stall() {
+        // the client never reads, so only 16 bytes can be written
+        let (_client, mut server) = tokio::io::duplex(16);
+        let mut output = limited(0, 4);
+
+        output.push_raw(b"0123").unwrap();
+        output.flush(&mut server).await.unwrap();
+        output.push_raw(b"0123456789abcdef").unwrap();
+        assert!(output.flush(&mut server).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn data_the_peer_does_not_accept_stays_buffered() {
+        let (mut client, mut server) = tokio::io::duplex(16);
+        let mut output = limited(0, 0);
+
+        output.push_raw(&[1; 24]).unwrap();
+        output.write_available(&mut server).unwrap();
+        assert_eq!(output.len(), 8);
+
+        let mut read = [0; 16];
+        client.read_exact(&mut read).await.unwrap();
+        output.write_some(&mut server).await.unwrap();
+        assert_eq!(output.len(), 0);
+        output.write_some(&mut server).await.unwrap();
+        assert!(!output.is_pending());
+    }
+
+    #[tokio::test]
+    async fn soft_limit_fails_buffers_that_stay_above_it() {
+        let (_client, mut server) = tokio::io::duplex(4);
+        let mut output = limited(0, 4);
+
+        output.push_raw(&[1; 16]).unwrap();
+        output.write_available(&mut server).unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(output.check_soft_limit().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use strum::{Display, IntoStaticStr};
+
+/// Metadata of a command, used to check its arity before it's handled
+/// and to describe it in `COMMAND` replies.
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    /// Subcommands are named after their container, e.g. `config|get`.
+    pub name: &'static str,
+    /// Number of arguments including the command name, negative values are the minimum.
+    pub arity: i32,
+    pub flags: &'static [CommandFlag],
+    pub keys: Keys,
+    /// ACL categories, e.g. `@read`.
+    pub categories: &'static [&'static str],
+    pub group: &'static str,
+    pub since: &'static str,
+    pub summary: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum CommandFlag {
+    Write,
+    Readonly,
+    Denyoom,
+    Admin,
+    Pubsub,
+    Noscript,
+    Blocking,
+    Fast,
+}
+
+/// Positions of keys in the arguments, indices include the command name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keys {
+    None,
+    /// Keys from `first` to `last` every `step` arguments, negative `last` counts from the end.
+    Range {
+        first: i32,
+        last: i32,
+        step: i32,
+    },
+    /// First half of the arguments after `keyword`, like the streams of `XREAD`.
+    Keyword {
+        keyword: &'static str,
+    },
+}
+
+impl Command {
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn is_subcommand(&self) -> bool {
+        self.name.contains('|')
+    }
+
+    /// Checks the number of arguments, including the command name.
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let arity = self.arity.unsigned_abs() as usize;
+        match self.arity < 0 {
+            true => argc >= arity,
+            false => argc == arity,
+        }
+    }
+
+    /// Keys in the arguments of a call, `args` include the command name.
+    pub fn keys<'a>(&self, args: &'a [String]) -> Vec<&'a str> {
+        match self.keys {
+            Keys::None => vec![],
+            Keys::Range { first, last, step } => {
+                let last = match last < 0 {
+                    true => args.len() as i32 + last,
+                    false => last.min(args.len() as i32 - 1),
+                };
+                (first..=last)
+                    .step_by(step.max(1) as usize)
+                    .filter_map(|i| args.get(i as usize))
+                    .map(|it| it.as_str())
+                    .collect()
+            }
+            Keys::Keyword { keyword } => {
+                let Some(pos) = args.iter().position(|it| it.eq_ignore_ascii_case(keyword)) else {
+                    return vec![];
+                };
+                let rest = &args[pos + 1..];
+                rest[..rest.len() / 2]
+                    .iter()
+                    .map(|it| it.as_str())
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Commands registered in a router, available to handlers as an extension.
+/// Subcommands are only found by name or through their container.
+#[derive(Debug, Clone, Default)]
+pub struct CommandTable(Arc<BTreeMap<&'static str, Command>>);
+
+impl CommandTable {
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.0.get(name.to_lowercase().as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Top level commands.
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.0.values().filter(|it| !it.is_subcommand())
+    }
+
+    /// Subcommands of the container `name`.
+    pub fn subcommands<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a Command> {
+        let prefix = format!("{}|", name.to_lowercase());
+        self.0.values().filter(move |it| {
+            it.name
+                .strip_prefix(&prefix)
+                .is_some_and(|it| !it.contains('|'))
+        })
+    }
+
+    pub(crate) fn all(&self) -> impl Iterator<Item = &Command> {
+        self.0.values()
+    }
+
+    pub(crate) fn insert(&mut self, command: Command) {
+        Arc::make_mut(&mut self.0).insert(command.name, command);
+    }
+}
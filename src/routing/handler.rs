@@ -12,13 +12,18 @@ use crate::{
     error::RedisError,
     request::FromRequest,
     response::IntoResponse,
-    routing::{Request, Response},
+    routing::{Request, Response, Router},
 };
 
 pub trait Handler<T>: Clone + Send + Sized + 'static {
     type Future: Future<Output = Result<Response, RedisError>> + Send + 'static;
 
     fn call(self, req: Request) -> Self::Future;
+
+    /// Router of the subcommands, for handlers of container commands.
+    fn nested(&self) -> Option<Router> {
+        None
+    }
 }
 
 pub trait HandlerExt<T>: Handler<T> {
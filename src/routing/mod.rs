@@ -4,14 +4,21 @@ use std::{
     task::{Context, Poll},
 };
 
-use tower::{Layer, Service};
+use futures_util::future::ready;
+use tower::{service_fn, Layer, Service};
 
-use crate::error::RedisError;
+use crate::{
+    error::RedisError,
+    response::{IntoResponse, Resp},
+};
 
+mod command;
 mod handler;
 mod route;
 
+use self::route::CheckArity;
 pub use self::{
+    command::{Command, CommandFlag, CommandTable, Keys},
     handler::{Handler, HandlerExt},
     route::{route_future::RouteFuture, Route},
 };
@@ -29,13 +36,26 @@ impl Router {
         }
     }
 
-    pub fn route<H, T>(self, path: &str, handler: H) -> Self
+    pub fn route<H, T>(self, command: Command, handler: H) -> Self
     where
         H: Handler<T>,
         T: 'static,
     {
         self.tap_inner_mut(|this| {
-            this.router.route(path, Route::new(handler.into_service()));
+            match handler.nested() {
+                Some(nested) => {
+                    for subcommand in nested.inner.commands.all() {
+                        this.commands.insert(*subcommand);
+                    }
+                    this.containers
+                        .insert(command.name, Container::new(command, nested));
+                }
+                None => this.router.route(
+                    command.name,
+                    Route::new(CheckArity::new(command, handler.into_service())),
+                ),
+            }
+            this.commands.insert(command);
         })
     }
 
@@ -49,6 +69,12 @@ impl Router {
         self.map_inner(|this| RouterInner {
             router: this.router.layer(layer.clone()),
             fallback: this.fallback.layer(layer.clone()),
+            commands: this.commands,
+            containers: this
+                .containers
+                .into_iter()
+                .map(|(name, container)| (name, container.layer(layer.clone())))
+                .collect(),
         })
     }
 
@@ -79,18 +105,66 @@ impl Router {
 struct RouterInner {
     router: CommandRouter,
     fallback: Route,
+    commands: CommandTable,
+    containers: HashMap<&'static str, Container>,
 }
 
 impl Default for RouterInner {
     fn default() -> Self {
-        async fn default_handler() -> Result<Response, RedisError> {
-            Err(RedisError::UnknownCommand)
+        async fn default_handler(request: Request) -> Result<Response, RedisError> {
+            Err(RedisError::UnknownCommand {
+                command: request.sent_command().to_owned(),
+                args: request.args,
+            })
         }
 
         Self {
             router: Default::default(),
             fallback: Route::new(default_handler.into_service()),
+            commands: Default::default(),
+            containers: Default::default(),
+        }
+    }
+}
+
+/// Command with subcommands, e.g. `CONFIG`. Its subcommands are layered on their own, so
+/// layers see `config|get` and run once per call.
+struct Container {
+    command: Command,
+    nested: Router,
+    /// Calls of the container itself with a wrong number of arguments, layered like a route.
+    rejected: Route,
+}
+
+impl Container {
+    fn new(command: Command, nested: Router) -> Self {
+        let wrong_arity = |req: Request| ready(Err(RedisError::WrongArity(req.command)));
+        Self {
+            command,
+            nested,
+            rejected: Route::new(service_fn(wrong_arity)),
+        }
+    }
+
+    fn layer<L>(self, layer: L) -> Container
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service:
+            Service<Request, Response = Response, Error = RedisError> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        Container {
+            command: self.command,
+            nested: self.nested.layer(layer.clone()),
+            rejected: self.rejected.layer(layer),
+        }
+    }
+
+    fn call(&self, req: Request) -> RouteFuture {
+        if !self.command.check_arity(route::argc(&req)) {
+            return RouteFuture::from_future(self.rejected.clone().oneshot_inner(req));
         }
+        Handler::call(self.nested.clone(), req)
     }
 }
 
@@ -142,7 +216,17 @@ impl Service<Request> for Router {
     }
 
     #[inline]
-    fn call(&mut self, req: Request) -> Self::Future {
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        req.extensions_mut().insert(self.inner.commands.clone());
+        self.dispatch(req)
+    }
+}
+
+impl Router {
+    fn dispatch(&self, req: Request) -> RouteFuture {
+        if let Some(container) = self.inner.containers.get(req.command.as_str()) {
+            return container.call(req);
+        }
         let req = match self.inner.router.call(req) {
             Ok(future) => return future,
             Err(req) => req,
@@ -150,4 +234,56 @@ impl Service<Request> for Router {
 
         RouteFuture::from_future(self.inner.fallback.clone().oneshot_inner(req))
     }
+
+    /// Reply of `<COMMAND> HELP`, built from the summaries of the subcommands.
+    fn help(&self, container: &str) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            container.to_uppercase()
+        )];
+        for command in self.inner.commands.subcommands(container) {
+            let (_, name) = command.name.rsplit_once('|').unwrap_or(("", command.name));
+            lines.push(name.to_uppercase());
+            lines.push(format!("    {}", command.summary));
+        }
+        lines.push("HELP".to_owned());
+        lines.push("    Print this help.".to_owned());
+        lines
+    }
+}
+
+/// A router handling the subcommands of a container command, e.g. `CONFIG GET`.
+/// The subcommand is matched case-insensitively and removed from the arguments,
+/// handlers see it in the command name like `config|get`.
+impl Handler<Router> for Router {
+    type Future = RouteFuture;
+
+    fn call(self, mut req: Request) -> Self::Future {
+        if req.args.is_empty() {
+            let error = RedisError::WrongArity(req.command);
+            return RouteFuture::from_response(error.into_response());
+        }
+        let subcommand = req.args.remove(0);
+        let container = std::mem::take(&mut req.command);
+        req.command = format!("{}|{}", container, subcommand.to_lowercase());
+
+        if !self.inner.router.routes.contains_key(&req.command) {
+            let response = match subcommand.eq_ignore_ascii_case("help") {
+                true if req.args.is_empty() => Resp(self.help(&container)).into_response(),
+                true => RedisError::WrongArity(req.command).into_response(),
+                false => RedisError::UnknownSubcommand {
+                    command: container.to_uppercase(),
+                    subcommand,
+                }
+                .into_response(),
+            };
+            return RouteFuture::from_response(response);
+        }
+
+        self.dispatch(req)
+    }
+
+    fn nested(&self) -> Option<Router> {
+        Some(self.clone())
+    }
 }
@@ -1,6 +1,7 @@
 use std::task::{Context, Poll};
 
 use derive_more::DebugCustom;
+use futures_util::future::{ready, Either, Ready};
 use parking_lot::Mutex;
 use route_future::RouteFuture;
 use tower::{
@@ -8,7 +9,7 @@ use tower::{
     Layer, Service, ServiceExt,
 };
 
-use crate::{error::RedisError, request::Request, response::Response};
+use crate::{error::RedisError, request::Request, response::Response, routing::Command};
 
 #[derive(DebugCustom)]
 #[debug(fmt = "Route")]
@@ -63,6 +64,46 @@ impl Service<Request> for Route {
     }
 }
 
+/// Arguments of a call, including the command name. Subcommands count their containers
+/// as arguments.
+pub(crate) fn argc(req: &Request) -> usize {
+    req.command.split('|').count() + req.args.len()
+}
+
+/// Rejects calls with a wrong number of arguments before they reach the handler.
+#[derive(Clone)]
+pub(crate) struct CheckArity<S> {
+    command: Command,
+    inner: S,
+}
+
+impl<S> CheckArity<S> {
+    pub(crate) fn new(command: Command, inner: S) -> Self {
+        Self { command, inner }
+    }
+}
+
+impl<S> Service<Request> for CheckArity<S>
+where
+    S: Service<Request, Response = Response, Error = RedisError>,
+{
+    type Response = Response;
+    type Error = RedisError;
+    type Future = Either<Ready<Result<Response, RedisError>>, S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if !self.command.check_arity(argc(&req)) {
+            return Either::Left(ready(Err(RedisError::WrongArity(req.command))));
+        }
+        Either::Right(self.inner.call(req))
+    }
+}
+
 pub mod route_future {
     use std::{
         future::Future,
@@ -110,6 +151,14 @@ pub mod route_future {
                 kind: RouteFutureKind::Future { future },
             }
         }
+
+        pub(crate) fn from_response(response: Response) -> Self {
+            Self {
+                kind: RouteFutureKind::Response {
+                    response: Some(response),
+                },
+            }
+        }
     }
 
     impl Future for RouteFuture {
@@ -0,0 +1,224 @@
+use std::{future::Future, path::PathBuf, sync::Arc};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use tokio::{
+    select,
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, oneshot, Notify},
+    time::timeout,
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use crate::{
+    clients::SharedClients,
+    config::Config,
+    engine::SharedEngine,
+    error::RedisError,
+    replication::{master::ReplicationWaitQueue, ReplicationState, SharedTopology},
+};
+
+pub type ShutdownQueue = mpsc::Sender<(ShutdownOptions, oneshot::Sender<Result<(), RedisError>>)>;
+type ShutdownRequests = mpsc::Receiver<(ShutdownOptions, oneshot::Sender<Result<(), RedisError>>)>;
+
+/// Options of `SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT]`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShutdownOptions {
+    /// Overrides whether a snapshot is saved, by default it's saved when a db file is configured.
+    pub save: Option<bool>,
+    /// Don't wait for replicas to catch up.
+    pub now: bool,
+    /// Ignore errors that would otherwise prevent the shutdown.
+    pub force: bool,
+    /// Cancel a shutdown that is waiting for replicas.
+    pub abort: bool,
+}
+
+/// State shared with connections, so they stop once the server shuts down.
+#[derive(Clone, Default)]
+pub struct Connections {
+    pub closed: CancellationToken,
+    pub tracker: TaskTracker,
+    pub clients: SharedClients,
+}
+
+pub struct Shutdown {
+    pub config: Arc<Config>,
+    pub engine: SharedEngine,
+    pub state: ReplicationState,
+    pub topology: SharedTopology,
+    /// Only master waits for replicas to catch up.
+    pub waits: Option<ReplicationWaitQueue>,
+    pub connections: Connections,
+}
+
+impl Shutdown {
+    pub fn queue() -> (ShutdownQueue, ShutdownRequests) {
+        mpsc::channel(1)
+    }
+
+    /// Runs the server until it's shut down with `SHUTDOWN`, SIGTERM or SIGINT.
+    pub async fn run(
+        self,
+        serving: impl Future<Output = eyre::Result<()>>,
+        mut requests: ShutdownRequests,
+    ) -> eyre::Result<()> {
+        tokio::pin!(serving);
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut in_progress: Option<InProgress> = None;
+
+        loop {
+            select! {
+                result = &mut serving => return result,
+                _ = terminate.recv() => {
+                    tracing::warn!("Received SIGTERM, scheduling shutdown");
+                    in_progress.get_or_insert_with(|| self.prepare(ShutdownOptions::default(), None));
+                }
+                _ = interrupt.recv() => {
+                    tracing::warn!("Received SIGINT, scheduling shutdown");
+                    in_progress.get_or_insert_with(|| self.prepare(ShutdownOptions::default(), None));
+                }
+                Some((options, reply)) = requests.recv() => {
+                    if options.abort {
+                        let _ = reply.send(match in_progress.take() {
+                            Some(aborted) => {
+                                tracing::warn!("Shutdown aborted");
+                                aborted.reply(Err(RedisError::ShutdownFailed));
+                                Ok(())
+                            }
+                            None => Err(RedisError::NoShutdown),
+                        });
+                    } else if in_progress.is_some() {
+                        let _ = reply.send(Err(RedisError::ShutdownFailed));
+                    } else {
+                        tracing::warn!(?options, "User requested shutdown");
+                        in_progress = Some(self.prepare(options, Some(reply)));
+                    }
+                }
+                result = async { (&mut in_progress.as_mut().unwrap().prepared).await }, if in_progress.is_some() => {
+                    let shutdown = in_progress.take().unwrap();
+                    match result {
+                        Ok(()) => {
+                            self.stop(shutdown).await;
+                            return Ok(());
+                        }
+                        Err(error) => {
+                            tracing::error!(%error, "Errors trying to shut down the server");
+                            shutdown.reply(Err(RedisError::ShutdownFailed));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits for replicas and saves a snapshot, everything that can fail and cancel the shutdown.
+    fn prepare(
+        &self,
+        options: ShutdownOptions,
+        requester: Option<oneshot::Sender<Result<(), RedisError>>>,
+    ) -> InProgress {
+        let config = self.config.clone();
+        let engine = self.engine.clone();
+        let replicas = self.topology.replicas().len();
+        let waits = self.waits.clone().filter(|_| replicas > 0 && !options.now);
+        let db_file = match options.save {
+            Some(false) => None,
+            Some(true) => Some(
+                config
+                    .db_file()
+                    .unwrap_or_else(|| PathBuf::from("dump.rdb")),
+            ),
+            None => config.db_file(),
+        };
+
+        let prepared = async move {
+            if let Some(waits) = waits {
+                tracing::info!(replicas, timeout = ?config.shutdown_timeout, "Waiting for replicas before shutdown");
+                let caught_up = wait_for_replicas(&waits, replicas, &config).await;
+                if caught_up < replicas {
+                    tracing::warn!(caught_up, replicas, "Replicas didn't catch up before shutdown");
+                }
+            }
+
+            if let Some(path) = db_file {
+                tracing::info!(path = %path.display(), "Saving snapshot before shutdown");
+                if let Err(error) = engine.save(&path).await {
+                    tracing::error!(%error, "Failed to save snapshot before shutdown");
+                    if !options.force {
+                        return Err(error);
+                    }
+                }
+            }
+
+            Ok(())
+        }
+        .boxed();
+
+        InProgress {
+            prepared,
+            requester,
+        }
+    }
+
+    /// Stops accepting requests and lets connections finish the ones in flight.
+    async fn stop(&self, shutdown: InProgress) {
+        self.connections.closed.cancel();
+        self.connections.tracker.close();
+        // connection that requested the shutdown is closed without a reply
+        drop(shutdown);
+
+        if timeout(
+            self.config.shutdown_timeout,
+            self.connections.tracker.wait(),
+        )
+        .await
+        .is_err()
+        {
+            tracing::warn!(
+                connections = self.connections.tracker.len(),
+                "Connections didn't finish in time"
+            );
+        }
+        tracing::info!(offset = %self.state.offset(), "Redis is now ready to exit, bye bye...");
+    }
+}
+
+struct InProgress {
+    prepared: BoxFuture<'static, Result<(), RedisError>>,
+    requester: Option<oneshot::Sender<Result<(), RedisError>>>,
+}
+
+impl InProgress {
+    fn reply(self, result: Result<(), RedisError>) {
+        if let Some(requester) = self.requester {
+            let _ = requester.send(result);
+        }
+    }
+}
+
+/// Returns the number of replicas that acknowledged the current offset within `shutdown-timeout`.
+async fn wait_for_replicas(
+    waits: &ReplicationWaitQueue,
+    replicas: usize,
+    config: &Config,
+) -> usize {
+    let (tx, rx) = oneshot::channel();
+    let not_interested = Arc::new(Notify::new());
+
+    if waits
+        .send((replicas, tx, not_interested.clone()))
+        .await
+        .is_err()
+    {
+        return 0;
+    }
+
+    let deadline = tokio::time::sleep(config.shutdown_timeout);
+    tokio::spawn(async move {
+        deadline.await;
+        not_interested.notify_one();
+    });
+
+    rx.await.unwrap_or_default()
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tower::{Layer, Service};
+
+use crate::{config::Config, error::RedisError, request::Request, response::Response};
+
+/// Arguments kept per entry, including the command name.
+const MAX_ARGC: usize = 32;
+/// Bytes kept per argument.
+const MAX_STRING: usize = 128;
+
+pub type SharedSlowLog = Arc<SlowLog>;
+
+/// Commands that ran for longer than `slowlog-log-slower-than`, newest first.
+pub struct SlowLog {
+    threshold: Option<Duration>,
+    max_len: usize,
+    inner: Mutex<SlowLogInner>,
+}
+
+#[derive(Default)]
+struct SlowLogInner {
+    next_id: u64,
+    entries: VecDeque<SlowLogEntry>,
+}
+
+/// Entry of `SLOWLOG GET`: id, unix time, duration in microseconds, arguments,
+/// client address and name.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowLogEntry(u64, u64, u64, Vec<Bytes>, Bytes, Bytes);
+
+impl SlowLog {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            threshold: config.slowlog_log_slower_than,
+            max_len: config.slowlog_max_len,
+            inner: Default::default(),
+        }
+    }
+
+    /// Up to `count` entries, newest first.
+    pub fn get(&self, count: usize) -> Vec<SlowLogEntry> {
+        self.inner
+            .lock()
+            .entries
+            .iter()
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    pub fn reset(&self) {
+        self.inner.lock().entries.clear();
+    }
+
+    fn push(&self, args: Vec<Bytes>, duration: Duration, addr: String, name: Option<String>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut inner = self.inner.lock();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.push_front(SlowLogEntry(
+            id,
+            timestamp,
+            duration.as_micros() as u64,
+            args,
+            Bytes::from(addr),
+            Bytes::from(name.unwrap_or_default()),
+        ));
+        inner.entries.truncate(self.max_len);
+    }
+}
+
+/// Arguments of a request as they were sent, redacted and truncated like redis does.
+fn entry_args(request: &Request) -> Vec<Bytes> {
+    let argv = request.redacted_argv();
+    let argc = argv.len();
+    let kept = match argc > MAX_ARGC {
+        true => MAX_ARGC - 1,
+        false => argc,
+    };
+
+    let mut args = argv
+        .into_iter()
+        .take(kept)
+        .map(|arg| match arg.len() > MAX_STRING {
+            true => {
+                let truncated = String::from_utf8_lossy(&arg.as_bytes()[..MAX_STRING]);
+                let more = arg.len() - MAX_STRING;
+                Bytes::from(format!("{truncated}... ({more} more bytes)"))
+            }
+            false => Bytes::copy_from_slice(arg.as_bytes()),
+        })
+        .collect::<Vec<_>>();
+    if argc > kept {
+        args.push(Bytes::from(format!("... ({} more arguments)", argc - kept)));
+    }
+    args
+}
+
+/// Records the calls slower than the threshold in the [`SlowLog`].
+#[derive(Clone)]
+pub struct SlowLogLayer(pub SharedSlowLog);
+
+impl<S> Layer<S> for SlowLogLayer {
+    type Service = RecordSlowLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordSlowLog {
+            inner,
+            slowlog: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordSlowLog<S> {
+    inner: S,
+    slowlog: SharedSlowLog,
+}
+
+impl<S> Service<Request> for RecordSlowLog<S>
+where
+    S: Service<Request, Response = Response, Error = RedisError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = RedisError;
+    type Future = BoxFuture<'static, Result<Response, RedisError>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let Some(threshold) = self.slowlog.threshold else {
+            return Box::pin(self.inner.call(req));
+        };
+        let slowlog = self.slowlog.clone();
+        let args = entry_args(&req);
+        let state = req.state().clone();
+        let blocked = state.blocked_time();
+        let start = Instant::now();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            // like redis, only the execution is measured, not the time the client was blocked
+            let duration = start
+                .elapsed()
+                .saturating_sub(state.blocked_time() - blocked);
+            if duration >= threshold {
+                slowlog.push(args, duration, state.addr().to_string(), state.name());
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::state::ConnectionState;
+
+    fn logged(argv: &[&str]) -> Vec<Bytes> {
+        let state = ConnectionState::new(SocketAddr::from(([127, 0, 0, 1], 60866)).into());
+        let request =
+            Request::from_command_line(argv.iter().map(|it| it.to_string()).collect(), state)
+                .unwrap();
+        entry_args(&request)
+    }
+
+    #[test]
+    fn long_arguments_are_truncated() {
+        let long = "a".repeat(MAX_STRING + 10);
+        let args = logged(&["SET", "key", &long]);
+        assert_eq!(args.len(), 3);
+        assert_eq!(
+            args[2],
+            format!("{}... (10 more bytes)", &long[..MAX_STRING])
+        );
+
+        let exact = "a".repeat(MAX_STRING);
+        assert_eq!(logged(&["SET", "key", &exact])[2], exact);
+    }
+
+    #[test]
+    fn extra_arguments_are_summarized() {
+        let values = (0..40).map(|it| it.to_string()).collect::<Vec<_>>();
+        let argv = ["RPUSH", "list"]
+            .into_iter()
+            .chain(values.iter().map(|it| it.as_str()))
+            .collect::<Vec<_>>();
+        let args = logged(&argv);
+        assert_eq!(args.len(), MAX_ARGC);
+        assert_eq!(args[0], "rpush");
+        assert_eq!(args[MAX_ARGC - 2], values[MAX_ARGC - 4]);
+        assert_eq!(args[MAX_ARGC - 1], "... (11 more arguments)");
+
+        let fitting = logged(&argv[..MAX_ARGC]);
+        assert_eq!(fitting.len(), MAX_ARGC);
+        assert_eq!(fitting[MAX_ARGC - 1], argv[MAX_ARGC - 1]);
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        assert_eq!(logged(&["AUTH", "secret"]), ["auth", "(redacted)"]);
+    }
+}
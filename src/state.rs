@@ -1,11 +1,22 @@
-use std::{fmt, net::SocketAddr, sync::Arc};
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use parking_lot::Mutex;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
+    config::ClientClass,
+    encoding::Protocol,
     error::RedisError,
-    network::NodeId,
+    network::{NodeId, PeerAddr},
     request::{FromRequest, Request},
 };
 
@@ -13,7 +24,7 @@ use crate::{
 pub struct ConnectionState(Arc<Mutex<ConnectionStateInner>>);
 
 impl ConnectionState {
-    pub fn new(addr: SocketAddr) -> Self {
+    pub fn new(addr: PeerAddr) -> Self {
         Self(Arc::new(Mutex::new(ConnectionStateInner::new(addr))))
     }
 
@@ -25,27 +36,173 @@ impl ConnectionState {
         self.0.lock().node_id = Some(id);
     }
 
-    pub fn addr(&self) -> SocketAddr {
-        self.0.lock().addr
+    pub fn addr(&self) -> PeerAddr {
+        self.0.lock().addr.clone()
+    }
+
+    /// Unique id of the connection, assigned in order of acceptance.
+    pub fn id(&self) -> u64 {
+        self.0.lock().id
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.0.lock().protocol
+    }
+
+    pub fn set_protocol(&self, protocol: Protocol) {
+        self.0.lock().protocol = protocol;
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.0.lock().name.clone()
+    }
+
+    /// Empty name removes the current one.
+    pub fn set_name(&self, name: String) {
+        self.0.lock().name = Some(name).filter(|it| !it.is_empty());
+    }
+
+    /// Connections that registered as replicas, all others are normal clients.
+    pub fn class(&self) -> ClientClass {
+        match self.node_id() {
+            Some(_) => ClientClass::Replica,
+            None => ClientClass::Normal,
+        }
+    }
+
+    /// Seconds since the connection was accepted.
+    pub fn age(&self) -> u64 {
+        self.0.lock().created.elapsed().as_secs()
+    }
+
+    /// Records a command about to be executed.
+    pub fn set_last_command(&self, command: &str) {
+        let mut inner = self.0.lock();
+        inner.last_command = Some(command.to_owned());
+        inner.last_interaction = Instant::now();
+    }
+
+    /// Waits for `future` with the client blocked, like `WAIT` or `XREAD BLOCK` do. Time spent
+    /// blocked isn't part of the execution time of the command.
+    pub async fn blocked_on<F: Future>(&self, future: F) -> F::Output {
+        let start = Instant::now();
+        let output = future.await;
+        self.0.lock().blocked += start.elapsed();
+        output
+    }
+
+    /// Total time the client spent blocked, see [`Self::blocked_on`].
+    pub fn blocked_time(&self) -> Duration {
+        self.0.lock().blocked
+    }
+
+    pub fn set_buffers(&self, query: usize, output: usize) {
+        let mut inner = self.0.lock();
+        inner.query_buffer = query;
+        inner.output_buffer = output;
+    }
+
+    /// Sizes of the query and output buffers.
+    pub fn buffers(&self) -> (usize, usize) {
+        let inner = self.0.lock();
+        (inner.query_buffer, inner.output_buffer)
+    }
+
+    pub fn set_no_evict(&self, no_evict: bool) {
+        self.0.lock().no_evict = no_evict;
+    }
+
+    pub fn set_monitor(&self) {
+        self.0.lock().monitor = true;
+    }
+
+    /// Cancelled when the connection should be closed by `CLIENT KILL`.
+    pub fn killed(&self) -> CancellationToken {
+        self.0.lock().killed.clone()
+    }
+
+    /// Line of `CLIENT LIST` and `CLIENT INFO` describing the connection.
+    pub fn describe(&self) -> String {
+        let inner = self.0.lock();
+        let mut flags = String::new();
+        if inner.node_id.is_some() {
+            flags.push('S');
+        }
+        if inner.monitor {
+            flags.push('O');
+        }
+        if inner.no_evict {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        format!(
+            "id={} addr={} name={} age={} idle={} flags={flags} db=0 qbuf={} obl={} cmd={} user=default resp={}",
+            inner.id,
+            inner.addr,
+            inner.name.as_deref().unwrap_or_default(),
+            inner.created.elapsed().as_secs(),
+            inner.last_interaction.elapsed().as_secs(),
+            inner.query_buffer,
+            inner.output_buffer,
+            inner.last_command.as_deref().unwrap_or("NULL"),
+            match inner.protocol {
+                Protocol::Resp2 => 2,
+                Protocol::Resp3 => 3,
+            },
+        )
     }
 }
 
+/// Names are shown in `CLIENT LIST`, so they can't contain spaces or special characters.
+pub fn is_valid_name(name: &str) -> bool {
+    name.chars().all(|c| ('!'..='~').contains(&c))
+}
+
 impl fmt::Debug for ConnectionState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "ConnectionState")
     }
 }
 
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
 struct ConnectionStateInner {
-    addr: SocketAddr,
+    id: u64,
+    addr: PeerAddr,
     node_id: Option<NodeId>,
+    protocol: Protocol,
+    name: Option<String>,
+    created: Instant,
+    last_interaction: Instant,
+    last_command: Option<String>,
+    blocked: Duration,
+    query_buffer: usize,
+    output_buffer: usize,
+    no_evict: bool,
+    monitor: bool,
+    killed: CancellationToken,
 }
 
 impl ConnectionStateInner {
-    pub fn new(addr: SocketAddr) -> Self {
+    pub fn new(addr: PeerAddr) -> Self {
         Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
             addr,
             node_id: None,
+            protocol: Protocol::default(),
+            name: None,
+            created: Instant::now(),
+            last_interaction: Instant::now(),
+            last_command: None,
+            blocked: Duration::ZERO,
+            query_buffer: 0,
+            output_buffer: 0,
+            no_evict: false,
+            monitor: false,
+            killed: CancellationToken::new(),
         }
     }
 }
@@ -56,3 +213,26 @@ impl FromRequest for ConnectionState {
         Ok(request.state().clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn blocked_time_adds_up() {
+        let state = ConnectionState::new(SocketAddr::from(([127, 0, 0, 1], 60866)).into());
+        assert_eq!(state.blocked_time(), Duration::ZERO);
+
+        let output = state.blocked_on(async { 42 }).await;
+        assert_eq!(output, 42);
+        state
+            .blocked_on(tokio::time::sleep(Duration::from_millis(20)))
+            .await;
+        state
+            .blocked_on(tokio::time::sleep(Duration::from_millis(20)))
+            .await;
+        assert!(state.blocked_time() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use tower::{Layer, Service};
+
+use crate::{error::RedisError, request::Request, response::Response, routing::CommandTable};
+
+pub type SharedStats = Arc<Stats>;
+
+/// Calls and errors per command, reported by `INFO commandstats` and `INFO errorstats`.
+pub struct Stats {
+    /// Random id of this run of the server.
+    pub run_id: String,
+    pub started: Instant,
+    commands: Mutex<BTreeMap<&'static str, CommandStats>>,
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            run_id: hex::encode(rand::random::<[u8; 20]>()),
+            started: Instant::now(),
+            commands: Default::default(),
+            errors: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    /// Calls rejected before the handler ran, e.g. because of their arity.
+    pub rejected_calls: u64,
+    /// Calls that were handled but replied with an error.
+    pub failed_calls: u64,
+}
+
+impl Stats {
+    fn record_rejected(&self, command: Option<&'static str>, code: &str) {
+        if let Some(command) = command {
+            self.commands
+                .lock()
+                .entry(command)
+                .or_default()
+                .rejected_calls += 1;
+        }
+        self.record_error(code);
+    }
+
+    fn record_call(&self, command: Option<&'static str>, elapsed: Duration, error: Option<String>) {
+        if let Some(command) = command {
+            let mut commands = self.commands.lock();
+            let stats = commands.entry(command).or_default();
+            stats.calls += 1;
+            stats.usec += elapsed.as_micros() as u64;
+            stats.failed_calls += error.is_some() as u64;
+        }
+        if let Some(code) = error {
+            self.record_error(&code);
+        }
+    }
+
+    fn record_error(&self, code: &str) {
+        *self.errors.lock().entry(code.to_owned()).or_default() += 1;
+    }
+
+    /// Stats of the commands that were called, by name.
+    pub fn commands(&self) -> Vec<(&'static str, CommandStats)> {
+        self.commands
+            .lock()
+            .iter()
+            .map(|(name, stats)| (*name, *stats))
+            .collect()
+    }
+
+    /// Error replies by code.
+    pub fn errors(&self) -> Vec<(String, u64)> {
+        self.errors
+            .lock()
+            .iter()
+            .map(|(code, count)| (code.clone(), *count))
+            .collect()
+    }
+
+    /// Calls of all the commands.
+    pub fn total_calls(&self) -> u64 {
+        self.commands.lock().values().map(|it| it.calls).sum()
+    }
+
+    /// Error replies of all the commands, including rejected calls.
+    pub fn total_errors(&self) -> u64 {
+        self.errors.lock().values().sum()
+    }
+
+    /// `CONFIG RESETSTAT`
+    pub fn reset(&self) {
+        self.commands.lock().clear();
+        self.errors.lock().clear();
+    }
+
+    pub fn write_commandstats(&self, output: &mut String) {
+        writeln!(output, "# Commandstats").unwrap();
+        for (name, stats) in self.commands.lock().iter() {
+            let per_call = match stats.calls {
+                0 => 0.0,
+                calls => stats.usec as f64 / calls as f64,
+            };
+            writeln!(
+                output,
+                "cmdstat_{name}:calls={},usec={},usec_per_call={per_call:.2},rejected_calls={},failed_calls={}",
+                stats.calls, stats.usec, stats.rejected_calls, stats.failed_calls,
+            )
+            .unwrap();
+        }
+    }
+
+    pub fn write_errorstats(&self, output: &mut String) {
+        writeln!(output, "# Errorstats").unwrap();
+        for (code, count) in self.errors.lock().iter() {
+            writeln!(output, "errorstat_{code}:count={count}").unwrap();
+        }
+    }
+}
+
+/// Records the calls of the routes it's applied to in [`Stats`].
+#[derive(Clone)]
+pub struct StatsLayer(pub SharedStats);
+
+impl<S> Layer<S> for StatsLayer {
+    type Service = RecordStats<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordStats {
+            inner,
+            stats: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordStats<S> {
+    inner: S,
+    stats: SharedStats,
+}
+
+impl<S> Service<Request> for RecordStats<S>
+where
+    S: Service<Request, Response = Response, Error = RedisError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = RedisError;
+    type Future = BoxFuture<'static, Result<Response, RedisError>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // only registered commands are tracked, so unknown names can't grow the stats
+        let command = req
+            .extensions()
+            .get::<CommandTable>()
+            .and_then(|it| it.get(&req.command))
+            .map(|it| it.name);
+        let stats = self.stats.clone();
+        let state = req.state().clone();
+        let blocked = state.blocked_time();
+        let start = Instant::now();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            // time spent blocked, e.g. in `WAIT`, isn't counted like in redis
+            let elapsed = start
+                .elapsed()
+                .saturating_sub(state.blocked_time() - blocked);
+            match &result {
+                Err(error) if error.is_rejection() => stats.record_rejected(command, error.code()),
+                Err(error) => stats.record_call(command, elapsed, Some(error.code().to_owned())),
+                Ok(response) => stats.record_call(command, elapsed, response.error_code()),
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{commands::table, request::ArgParse, routing::Router, state::ConnectionState};
+
+    async fn call(router: &Router, argv: &[&str]) {
+        let state = ConnectionState::new(SocketAddr::from(([127, 0, 0, 1], 60866)).into());
+        let request =
+            Request::from_command_line(argv.iter().map(|it| it.to_string()).collect(), state)
+                .unwrap();
+        let _ = router.clone().oneshot(request).await;
+    }
+
+    fn command_stats(stats: &Stats, command: &str) -> CommandStats {
+        let (_, stats) = stats
+            .commands()
+            .into_iter()
+            .find(|(name, _)| *name == command)
+            .unwrap();
+        stats
+    }
+
+    fn calls(stats: &Stats, command: &str) -> (u64, u64, u64) {
+        let stats = command_stats(stats, command);
+        (stats.calls, stats.rejected_calls, stats.failed_calls)
+    }
+
+    #[tokio::test]
+    async fn only_calls_that_did_not_run_are_rejected() {
+        async fn get(ArgParse(_): ArgParse<u64, 1>) -> &'static str {
+            "OK"
+        }
+        async fn config_get() -> &'static str {
+            "OK"
+        }
+
+        let stats = SharedStats::default();
+        let router = Router::new()
+            .route(table::GET, get)
+            .route(
+                table::CONFIG,
+                Router::new().route(table::CONFIG_GET, config_get),
+            )
+            .layer(StatsLayer(stats.clone()));
+
+        call(&router, &["GET", "1"]).await;
+        call(&router, &["GET", "not a number"]).await;
+        call(&router, &["GET"]).await;
+        assert_eq!(calls(&stats, "get"), (2, 1, 1));
+
+        call(&router, &["CONFIG", "GET", "port"]).await;
+        call(&router, &["CONFIG"]).await;
+        assert_eq!(calls(&stats, "config|get"), (1, 0, 0));
+        assert_eq!(calls(&stats, "config"), (0, 1, 0));
+        assert_eq!(stats.total_errors(), 3);
+    }
+
+    #[tokio::test]
+    async fn blocked_time_is_not_counted() {
+        async fn wait(connection: ConnectionState) -> &'static str {
+            connection
+                .blocked_on(tokio::time::sleep(Duration::from_millis(50)))
+                .await;
+            "OK"
+        }
+
+        let stats = SharedStats::default();
+        let router = Router::new()
+            .route(table::WAIT, wait)
+            .layer(StatsLayer(stats.clone()));
+
+        call(&router, &["WAIT", "1", "50"]).await;
+        let stats = command_stats(&stats, "wait");
+        assert_eq!(stats.calls, 1);
+        assert!(stats.usec < 50_000, "{} usec", stats.usec);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::{btree_map::Entry, BTreeMap, HashMap},
-    time::SystemTime,
+    ops::Bound,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::value::RedisValue;
@@ -9,6 +10,10 @@ use crate::value::RedisValue;
 pub struct Memory {
     aux: HashMap<String, String>,
     data: BTreeMap<String, (RedisValue, Option<SystemTime>)>,
+    /// Keys with an expiration, and the sum of their expirations in milliseconds since the
+    /// epoch, kept up to date so `INFO keyspace` doesn't scan the data.
+    expires: usize,
+    expirations_sum: u128,
 }
 
 impl Memory {
@@ -23,6 +28,32 @@ impl Memory {
 
         SystemTime::now() > exp
     }
+
+    fn track(&mut self, expiration: Option<SystemTime>) {
+        if let Some(millis) = expiration.map(millis_since_epoch) {
+            self.expires += 1;
+            self.expirations_sum += millis;
+        }
+    }
+
+    fn untrack(&mut self, expiration: Option<SystemTime>) {
+        if let Some(millis) = expiration.map(millis_since_epoch) {
+            self.expires -= 1;
+            self.expirations_sum -= millis;
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, expiration)) = self.data.remove(key) {
+            self.untrack(expiration);
+        }
+    }
+}
+
+fn millis_since_epoch(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
 }
 
 impl super::Storage for Memory {
@@ -30,6 +61,21 @@ impl super::Storage for Memory {
         Ok(self.data.keys().map(|it| it.as_ref()))
     }
 
+    fn entries_after(
+        &mut self,
+        after: Option<&str>,
+    ) -> eyre::Result<impl IntoIterator<Item = (&str, &RedisValue, Option<SystemTime>)>> {
+        let start = match after {
+            Some(key) => Bound::Excluded(key),
+            None => Bound::Unbounded,
+        };
+        Ok(self
+            .data
+            .range::<str, _>((start, Bound::Unbounded))
+            .filter(|(_, (_, exp))| !Self::is_expired(*exp))
+            .map(|(k, (v, exp))| (k.as_str(), v, *exp)))
+    }
+
     fn get(&mut self, key: &str) -> eyre::Result<Option<RedisValue>> {
         let Some((v, expiration)) = self.data.get(key) else {
             return Ok(None);
@@ -46,7 +92,7 @@ impl super::Storage for Memory {
     fn get_mut(&mut self, key: &str) -> eyre::Result<Option<&mut RedisValue>> {
         match self.data.get(key) {
             Some((_, exp)) if Self::is_expired(*exp) => {
-                self.data.remove(key);
+                self.remove(key);
                 return Ok(None);
             }
             None => return Ok(None),
@@ -66,7 +112,11 @@ impl super::Storage for Memory {
         match self.data.entry(key.to_owned()) {
             Entry::Occupied(o) if !Self::is_expired(o.get().1) => Ok(&mut o.into_mut().0),
             Entry::Occupied(mut o) => {
-                o.insert((value(), None));
+                let (_, expiration) = o.insert((value(), None));
+                if let Some(millis) = expiration.map(millis_since_epoch) {
+                    self.expires -= 1;
+                    self.expirations_sum -= millis;
+                }
                 Ok(&mut o.into_mut().0)
             }
             Entry::Vacant(v) => Ok(&mut v.insert((value(), None)).0),
@@ -79,16 +129,79 @@ impl super::Storage for Memory {
         value: RedisValue,
         expiration: Option<SystemTime>,
     ) -> eyre::Result<()> {
-        self.data.insert(key.to_owned(), (value, expiration));
+        if let Some((_, replaced)) = self.data.insert(key.to_owned(), (value, expiration)) {
+            self.untrack(replaced);
+        }
+        self.track(expiration);
         Ok(())
     }
 
     fn delete(&mut self, key: &str) -> eyre::Result<()> {
-        self.data.remove(key);
+        self.remove(key);
         Ok(())
     }
 
     fn flush(&mut self) -> eyre::Result<()> {
         Ok(())
     }
+
+    fn key_count(&self) -> usize {
+        self.data.len()
+    }
+
+    fn expirations(&self) -> (usize, Option<SystemTime>) {
+        let average = (self.expires > 0).then(|| {
+            let millis = self.expirations_sum / self.expires as u128;
+            UNIX_EPOCH + Duration::from_millis(millis as u64)
+        });
+        (self.expires, average)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn value() -> RedisValue {
+        RedisValue::String("value".to_owned())
+    }
+
+    #[test]
+    fn expirations_follow_sets_and_deletes() {
+        let in_a_minute = SystemTime::now() + Duration::from_secs(60);
+        let in_three_minutes = SystemTime::now() + Duration::from_secs(180);
+        let mut memory = Memory::default();
+
+        memory.set("a", value(), None).unwrap();
+        memory.set("b", value(), Some(in_a_minute)).unwrap();
+        memory.set("c", value(), Some(in_three_minutes)).unwrap();
+        let (expires, average) = memory.expirations();
+        assert_eq!((memory.key_count(), expires), (3, 2));
+        let average = average.unwrap().duration_since(SystemTime::now()).unwrap();
+        assert!(average <= Duration::from_secs(120) && average > Duration::from_secs(119));
+
+        memory.set("b", value(), None).unwrap();
+        memory.delete("c").unwrap();
+        memory.delete("missing").unwrap();
+        assert_eq!(memory.key_count(), 2);
+        assert_eq!(memory.expirations(), (0, None));
+    }
+
+    #[test]
+    fn expired_keys_are_untracked_when_removed() {
+        let mut memory = Memory::default();
+        memory
+            .set(
+                "a",
+                value(),
+                Some(SystemTime::now() - Duration::from_secs(1)),
+            )
+            .unwrap();
+        assert_eq!(memory.expirations().0, 1);
+
+        assert!(memory.get("a").unwrap().is_none());
+        assert_eq!(memory.key_count(), 0);
+        assert_eq!(memory.expirations(), (0, None));
+    }
 }
@@ -11,6 +11,12 @@ use crate::value::RedisValue;
 
 pub trait Storage: fmt::Debug + Send + Sync {
     fn get_keys(&mut self) -> Result<impl IntoIterator<Item = &str>>;
+    /// Iterates in key order over the entries that are not expired, along with their
+    /// expiration, starting after the key `after`, or from the first one.
+    fn entries_after(
+        &mut self,
+        after: Option<&str>,
+    ) -> Result<impl IntoIterator<Item = (&str, &RedisValue, Option<SystemTime>)>>;
     /// Gets a value for a key, if it exists.
     fn get(&mut self, key: &str) -> Result<Option<RedisValue>>;
     fn get_mut(&mut self, key: &str) -> Result<Option<&mut RedisValue>>;
@@ -28,4 +34,10 @@ pub trait Storage: fmt::Debug + Send + Sync {
 
     /// Flushes any buffered data to the underlying storage medium.
     fn flush(&mut self) -> Result<()>;
+
+    /// Number of keys, including expired ones that weren't removed yet.
+    fn key_count(&self) -> usize;
+
+    /// Number of keys with an expiration, along with the average of their expirations.
+    fn expirations(&self) -> (usize, Option<SystemTime>);
 }
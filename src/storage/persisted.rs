@@ -67,6 +67,13 @@ impl super::Storage for Persisted {
         self.memory.get_keys()
     }
 
+    fn entries_after(
+        &mut self,
+        after: Option<&str>,
+    ) -> eyre::Result<impl IntoIterator<Item = (&str, &RedisValue, Option<SystemTime>)>> {
+        self.memory.entries_after(after)
+    }
+
     fn get(&mut self, key: &str) -> eyre::Result<Option<RedisValue>> {
         self.memory.get(key)
     }
@@ -99,4 +106,12 @@ impl super::Storage for Persisted {
     fn flush(&mut self) -> eyre::Result<()> {
         todo!()
     }
+
+    fn key_count(&self) -> usize {
+        self.memory.key_count()
+    }
+
+    fn expirations(&self) -> (usize, Option<SystemTime>) {
+        self.memory.expirations()
+    }
 }
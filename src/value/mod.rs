@@ -27,7 +27,6 @@ pub enum ValueType {
 }
 
 impl ValueType {
-    #[allow(dead_code)]
     pub fn into_u8(self) -> u8 {
         match self {
             ValueType::String => 0,
@@ -6,7 +6,6 @@ use std::{
 };
 
 use derive_more::{Display, From, Into};
-use eyre::eyre;
 use serde::Serialize;
 
 use crate::error::RedisError;
@@ -35,14 +34,14 @@ impl FromStr for StreamId {
             _ => {}
         }
         let Some((ts, c)) = s.split_once("-") else {
-            return Err(RedisError::Unhandled(eyre!("Could not parse stream id")));
+            return Err(RedisError::InvalidStreamId);
         };
 
-        let ts = ts.parse().map_err(|_| eyre!("Could not parse stream id"))?;
+        let ts = ts.parse().map_err(|_| RedisError::InvalidStreamId)?;
 
         let c = match c {
             "*" => u64::MAX,
-            _ => c.parse().map_err(|_| eyre!("Could not parse stream id"))?,
+            _ => c.parse().map_err(|_| RedisError::InvalidStreamId)?,
         };
         Ok(StreamId::from((ts, c)))
     }
@@ -107,12 +106,10 @@ impl Stream {
 
     pub fn append(&mut self, key: StreamId, value: Vec<String>) -> Result<StreamId, RedisError> {
         if key == StreamId(0, 0) {
-            return Err(eyre!("ERR The ID specified in XADD must be greater than 0-0").into());
+            return Err(RedisError::StreamIdZero);
         }
         if key <= self.last_id {
-            return Err(RedisError::Unhandled(eyre!(
-                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
-            )));
+            return Err(RedisError::StreamIdTooSmall);
         }
         let key = self.map_key_allocation(key);
 